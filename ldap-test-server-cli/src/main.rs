@@ -150,6 +150,7 @@ o: ldap-test-server-cli"
     );
 
     println!("SSL Certificate:\n {}", server.ssl_cert_pem());
+    println!("CA Certificate:\n {}", server.ca_cert_pem());
 
     info!("waiting for ctrl-c");
     signal::ctrl_c().await.expect("failed to listen for event");
@@ -0,0 +1,147 @@
+//! Attribute macro declaring OpenLDAP server fixtures for
+//! [ldap-test-server](https://docs.rs/ldap-test-server) crate.
+//!
+//! Use it through `macros` feature of `ldap-test-server`, generated code refers to
+//! `ldap_test_server` and `tokio` crates.
+#![warn(missing_docs)]
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Error, Expr, FnArg, ItemFn, Lit, LitStr, MetaNameValue, Token};
+
+/// Run test function with LDAP server
+///
+/// Builds [`LdapServerBuilder`](https://docs.rs/ldap-test-server/latest/ldap_test_server/struct.LdapServerBuilder.html),
+/// starts server and passes `LdapServerConn` as the only parameter of async test function.
+/// Test is skipped (passes with message on stderr) when OpenLDAP is not installed.
+///
+/// Arguments:
+///  - `base_dn` - base DN of database 1, required
+///  - `schema` - system schema LDIFs added to database 0, e.g. `["pmi.ldif"]`
+///  - `ldif` - LDIF file or array of files added to database 1, relative to `CARGO_MANIFEST_DIR`
+///
+/// # Examples
+///
+/// ```ignore
+/// use ldap_test_server::{ldap_test, LdapServerConn};
+///
+/// #[ldap_test(base_dn = "dc=planetexpress,dc=com", schema = ["pmi.ldif"], ldif = "tests/planetexpress.ldif")]
+/// async fn test_search(server: LdapServerConn) {
+///     assert!(server.exists("dc=planetexpress,dc=com").await);
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn ldap_test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as Args);
+    let item = parse_macro_input!(item as ItemFn);
+    expand(args, item)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct Args {
+    base_dn: Option<LitStr>,
+    schema: Vec<LitStr>,
+    ldif: Vec<LitStr>,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = Args::default();
+        for arg in Punctuated::<MetaNameValue, Token![,]>::parse_terminated(input)? {
+            let name = arg
+                .path
+                .get_ident()
+                .map(|ident| ident.to_string())
+                .unwrap_or_default();
+            match name.as_str() {
+                "base_dn" => args.base_dn = Some(string(&arg.value)?),
+                "schema" => args.schema = strings(&arg.value)?,
+                "ldif" => args.ldif = strings(&arg.value)?,
+                _ => {
+                    return Err(Error::new_spanned(
+                        &arg.path,
+                        "unknown argument, expected one of: base_dn, schema, ldif",
+                    ))
+                }
+            }
+        }
+        Ok(args)
+    }
+}
+
+fn string(expr: &Expr) -> syn::Result<LitStr> {
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Str(s) => Ok(s.clone()),
+            _ => Err(Error::new_spanned(expr, "expected string literal")),
+        },
+        _ => Err(Error::new_spanned(expr, "expected string literal")),
+    }
+}
+
+/// Single string or array of strings
+fn strings(expr: &Expr) -> syn::Result<Vec<LitStr>> {
+    match expr {
+        Expr::Array(array) => array.elems.iter().map(string).collect(),
+        _ => Ok(vec![string(expr)?]),
+    }
+}
+
+fn expand(args: Args, mut item: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let base_dn = args
+        .base_dn
+        .ok_or_else(|| Error::new(Span::call_site(), "missing `base_dn` argument"))?;
+    if item.sig.asyncness.is_none() {
+        return Err(Error::new_spanned(
+            item.sig.fn_token,
+            "#[ldap_test] function must be async",
+        ));
+    }
+    if item.sig.inputs.len() > 1 {
+        return Err(Error::new_spanned(
+            &item.sig.inputs,
+            "#[ldap_test] function takes at most one parameter: LdapServerConn",
+        ));
+    }
+
+    let server = match item.sig.inputs.pop() {
+        Some(param) => match param.into_value() {
+            FnArg::Typed(param) => {
+                let (pat, ty) = (param.pat, param.ty);
+                quote!(let #pat: #ty = builder.run().await;)
+            }
+            FnArg::Receiver(receiver) => {
+                return Err(Error::new_spanned(receiver, "unexpected self parameter"))
+            }
+        },
+        None => quote!(let _server = builder.run().await;),
+    };
+
+    let schema = args.schema;
+    let ldif = args.ldif;
+    let name = item.sig.ident.to_string();
+    let attrs = &item.attrs;
+    let vis = &item.vis;
+    let sig = &item.sig;
+    let body = &item.block;
+
+    Ok(quote! {
+        #[::tokio::test]
+        #(#attrs)*
+        #vis #sig {
+            if !::ldap_test_server::is_installed() {
+                ::std::eprintln!("skipping {}: OpenLDAP (slapd) is not installed", #name);
+                return;
+            }
+            let builder = ::ldap_test_server::LdapServerBuilder::new(#base_dn)
+                #(.add_system_file(0, #schema))*
+                #(.add_file(1, ::std::concat!(::std::env!("CARGO_MANIFEST_DIR"), "/", #ldif)))*;
+            #server
+            #body
+        }
+    })
+}
@@ -0,0 +1,14 @@
+use ldap_test_server::{ldap_test, LdapServerConn};
+
+#[ldap_test(
+    base_dn = "dc=planetexpress,dc=com",
+    schema = ["pmi.ldif"],
+    ldif = "tests/planetexpress.ldif"
+)]
+async fn test_server_param(server: LdapServerConn) {
+    assert_eq!(server.base_dn(), "dc=planetexpress,dc=com");
+    assert!(server.exists("ou=people,dc=planetexpress,dc=com").await);
+}
+
+#[ldap_test(base_dn = "dc=planetexpress,dc=com")]
+async fn test_without_param() {}
@@ -1,29 +1,49 @@
-use crate::LdapServerConn;
-use rand::Rng;
-use random_port::{PortPicker, Protocol};
-use rcgen::{CertificateParams, KeyPair, SanType};
-use std::net::{IpAddr, ToSocketAddrs};
+use crate::cache::{slapd_version, CacheKey, ConfigCache};
+use crate::cluster::LdapCluster;
+use crate::config::{self, Change};
+use crate::keep::KeepDir;
+use crate::log::{SlapdLog, SlapdLogLevel};
+use crate::overlay::{self, Overlay};
+use crate::ports::pick_port;
+use crate::ppolicy::{self, PasswordPolicy};
+use crate::proxy::LinkProxy;
+use crate::replication::{self, LdapReplicaSet, Syncrepl};
+use crate::sasl::{self, SaslMechanism, SASL_CONF_DIR};
+use crate::slapd::{run_dir, slapadd, SlapdCommand, OWNER_PID_FILE, PROCESS_FILES};
+use crate::tls::{
+    leaf_pem, regex_escape, CertificateAuthority, CertificatePreset, StartTls, TlsVerifyClient,
+    TlsVersion,
+};
+use crate::{LdapServerConn, LdapServerError};
+use dircpy::copy_dir;
+use std::io;
+use std::mem::ManuallyDrop;
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
-use std::str::FromStr;
-use std::time::Duration;
-use tempfile::tempdir;
-use tokio::fs;
-use tokio::io::AsyncBufReadExt;
-use tokio::net::TcpStream;
-use tokio::process::Command;
-use tokio::time::{sleep, timeout};
+use std::sync::Arc;
+use tempfile::tempdir_in;
+use tokio::{fs, task};
 use tracing::debug;
 use url::Url;
 
 const INIT_LDIF: &str = include_str!("init.ldif");
-const POSSIBLE_SCHEMA_DIR: &[&str] = &[
+const DATABASE_LDIF: &str = include_str!("database.ldif");
+const DEFAULT_PORT_RETRIES: u32 = 5;
+/// Names in generated server certificate in addition to listen address
+const DEFAULT_ALT_NAMES: &[&str] = &["localhost", "127.0.0.1", "::1"];
+pub(crate) const POSSIBLE_SCHEMA_DIR: &[&str] = &[
     "/etc/ldap/schema",
     "/usr/local/etc/openldap/schema",
     "/etc/openldap/schema/",
 ];
+const POSSIBLE_MODULE_DIR: &[&str] = &[
+    "/usr/lib/ldap",
+    "/usr/lib64/openldap",
+    "/usr/lib/openldap",
+    "/usr/libexec/openldap",
+    "/usr/local/libexec/openldap",
+];
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum LdapFile {
     SystemSchema(PathBuf),
     File { template: bool, file: PathBuf },
@@ -31,7 +51,7 @@ enum LdapFile {
 }
 
 /// LDAP server builder
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LdapServerBuilder {
     base_dn: String,
     root_dn: String,
@@ -41,6 +61,27 @@ pub struct LdapServerBuilder {
     ssl_port: Option<u16>,
     includes: Vec<(u8, LdapFile)>,
     ssl_cert_key: Option<(String, String)>,
+    ssl_alt_names: Vec<String>,
+    intermediate_ca: bool,
+    ssl_preset: CertificatePreset,
+    log_levels: Vec<SlapdLogLevel>,
+    source_dir: Option<PathBuf>,
+    cache: Option<ConfigCache>,
+    keep_dir: Option<KeepDir>,
+    port_retries: u32,
+    reserve_ports: bool,
+    ldapi: bool,
+    start_tls: StartTls,
+    tls_min_version: Option<TlsVersion>,
+    tls_cipher_suite: Option<String>,
+    tls_verify_client: TlsVerifyClient,
+    sasl_mechanisms: Vec<SaslMechanism>,
+    overlays: Vec<Overlay>,
+    password_policy: Option<PasswordPolicy>,
+    /// Numbers of databases added by `add_database`
+    databases: Vec<u8>,
+    /// Replication of servers started by `run_replicated` and `run_cluster`
+    syncrepl: Option<Syncrepl>,
 }
 
 impl LdapServerBuilder {
@@ -63,6 +104,25 @@ impl LdapServerBuilder {
             ssl_port: None,
             includes: vec![],
             ssl_cert_key: None,
+            ssl_alt_names: vec![],
+            intermediate_ca: false,
+            ssl_preset: CertificatePreset::Valid,
+            log_levels: vec![],
+            source_dir: None,
+            cache: None,
+            keep_dir: None,
+            port_retries: DEFAULT_PORT_RETRIES,
+            reserve_ports: false,
+            ldapi: false,
+            start_tls: StartTls::Allow,
+            tls_min_version: None,
+            tls_cipher_suite: None,
+            tls_verify_client: TlsVerifyClient::Never,
+            sasl_mechanisms: vec![],
+            overlays: vec![],
+            password_policy: None,
+            databases: vec![],
+            syncrepl: None,
         }
     }
 
@@ -73,7 +133,130 @@ impl LdapServerBuilder {
         LdapServerBuilder::empty(base_dn, root_dn, root_pw).add_template(0, INIT_LDIF)
     }
 
+    /// Init builder from server directory copied with [`LdapServerConn::clone_to_dir`]
+    ///
+    /// Configuration and database are copied to new temporary directory, paths in configuration
+    /// are updated to the new location and server is started on new ports. Base DN, root DN
+    /// and password are read from database 1 configuration. Certificate from `cert.pem` and
+    /// `key.pem` is reused unless [`LdapServerBuilder::ssl_certificates`] is set.
+    /// LDIFs added to this builder are applied on top of copied databases.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::LdapServerBuilder;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let golden = tempfile::tempdir().unwrap();
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .add(1, "dn: dc=planetexpress,dc=com
+    /// objectclass: dcObject
+    /// objectclass: organization
+    /// o: Planet Express
+    /// dc: planetexpress")
+    ///     .run().await;
+    /// server.clone_to_dir(golden.path()).await;
+    /// drop(server);
+    ///
+    /// let server = LdapServerBuilder::from_dir(golden.path()).run().await;
+    /// assert!(server.exists("dc=planetexpress,dc=com").await);
+    /// # }
+    /// ```
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Self {
+        let mut builder = LdapServerBuilder::empty("", "", "");
+        builder.source_dir = Some(dir.as_ref().to_path_buf());
+        builder
+    }
+
+    /// Reuse configuration and databases built by earlier server with the same includes
+    ///
+    /// See [`ConfigCache`] for details. Cache is not used with [`LdapServerBuilder::from_dir`].
+    pub fn cache(mut self, cache: ConfigCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Keep server directory after [`LdapServerConn`] is dropped, for inspecting
+    /// configuration and data of failed test
+    ///
+    /// Path of kept directory is logged as `tracing` warning together with slapd and
+    /// ldapsearch commands. Default is read from [`crate::KEEP_DIR_ENV`] environment variable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::{KeepDir, LdapServerBuilder};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .keep_dir(KeepDir::OnPanic)
+    ///     .run().await;
+    /// # }
+    /// ```
+    pub fn keep_dir(mut self, keep_dir: KeepDir) -> Self {
+        self.keep_dir = Some(keep_dir);
+        self
+    }
+
+    /// Add host name or IP address to generated server certificate
+    ///
+    /// Certificate is issued for listen address, `localhost`, `127.0.0.1` and `::1` by default.
+    pub fn ssl_alt_name(mut self, name: &str) -> Self {
+        self.ssl_alt_names.push(name.to_string());
+        self
+    }
+
+    /// Sign generated server certificate by intermediate CA instead of root CA
+    ///
+    /// Server sends intermediate CA certificate with its certificate, clients trusting
+    /// [`LdapServerConn::ca_cert_pem`] have to validate the whole chain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::LdapServerBuilder;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .intermediate_ca(true)
+    ///     .ssl_alt_name("ldap.planetexpress.com")
+    ///     .run().await;
+    /// assert!(server.intermediate_cert_pem().is_some());
+    /// assert!(server.chain_pem().ends_with(server.intermediate_cert_pem().unwrap()));
+    /// # }
+    /// ```
+    pub fn intermediate_ca(mut self, intermediate: bool) -> Self {
+        self.intermediate_ca = intermediate;
+        self
+    }
+
+    /// Generate invalid server certificate, default is [`CertificatePreset::Valid`]
+    ///
+    /// Preset is ignored when [`LdapServerBuilder::ssl_certificates`] is set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::{CertificatePreset, LdapServerBuilder};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .ssl_certificate_preset(CertificatePreset::Expired)
+    ///     .run().await;
+    /// # }
+    /// ```
+    pub fn ssl_certificate_preset(mut self, preset: CertificatePreset) -> Self {
+        self.ssl_preset = preset;
+        self
+    }
+
     /// Use existing ssl certificate and key PEM
+    ///
+    /// Certificate may be followed by intermediate CA certificates sent to clients.
     pub fn ssl_certificates(mut self, certificate: String, key: String) -> Self {
         self.ssl_cert_key = Some((certificate, key));
         self
@@ -85,6 +268,189 @@ impl LdapServerBuilder {
         self
     }
 
+    /// Listen also on `ldapi://` Unix domain socket in server directory
+    ///
+    /// Connections authenticated with SASL EXTERNAL mechanism by user running tests
+    /// are mapped to root DN. Operations like [`LdapServerConn::add`] use this socket
+    /// instead of TCP and password, except with [`StartTls::Require`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::LdapServerBuilder;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .ldapi(true)
+    ///     .run().await;
+    /// println!("ldapsearch -Y EXTERNAL -H {}", server.ldapi_url().unwrap());
+    /// # }
+    /// ```
+    pub fn ldapi(mut self, ldapi: bool) -> Self {
+        self.ldapi = ldapi;
+        self
+    }
+
+    /// StartTLS policy of plain `ldap://` listener, default is [`StartTls::Allow`]
+    ///
+    /// With [`StartTls::Require`] operations on connections without TLS are rejected,
+    /// including `ldapi://` socket. Operations like [`LdapServerConn::add`] use `ldaps://`
+    /// port then.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::{LdapServerBuilder, StartTls, TlsVersion};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .start_tls(StartTls::Require)
+    ///     .tls_min_version(TlsVersion::Tls1_2)
+    ///     .run().await;
+    /// # }
+    /// ```
+    pub fn start_tls(mut self, start_tls: StartTls) -> Self {
+        self.start_tls = start_tls;
+        self
+    }
+
+    /// Minimal TLS protocol version accepted by server (`olcTLSProtocolMin`)
+    pub fn tls_min_version(mut self, version: TlsVersion) -> Self {
+        self.tls_min_version = Some(version);
+        self
+    }
+
+    /// Allowed cipher suites (`olcTLSCipherSuite`), format depends on TLS library
+    /// slapd is linked with (OpenSSL cipher list or GnuTLS priority string)
+    pub fn tls_cipher_suite(mut self, cipher_suite: &str) -> Self {
+        self.tls_cipher_suite = Some(cipher_suite.to_string());
+        self
+    }
+
+    /// Client certificate verification (`olcTLSVerifyClient`), default is
+    /// [`TlsVerifyClient::Never`]
+    ///
+    /// Client certificates are verified with test CA generated for server, which issues
+    /// certificates with [`LdapServerConn::issue_client_cert`]. Clients presenting such
+    /// certificate may bind with SASL EXTERNAL mechanism as entry with certificate subject DN.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::{LdapServerBuilder, TlsVerifyClient};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .tls_verify_client(TlsVerifyClient::Try)
+    ///     .run().await;
+    /// let client = server
+    ///     .issue_client_cert("cn=Philip J. Fry,dc=planetexpress,dc=com")
+    ///     .await;
+    /// println!("{}{}", client.cert_pem(), client.key_pem());
+    /// # }
+    /// ```
+    pub fn tls_verify_client(mut self, verify_client: TlsVerifyClient) -> Self {
+        self.tls_verify_client = verify_client;
+        self
+    }
+
+    /// Enable SASL mechanism, can be called multiple times to enable more mechanisms
+    ///
+    /// Users are authenticated by clear text `userPassword` of entry with `uid` equal to
+    /// SASL user name found under base DN, see [`LdapServerConn::add_sasl_user`].
+    /// Cyrus SASL configuration is written to server directory, system-wide configuration
+    /// is not used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::{LdapServerBuilder, SaslMechanism};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .add(1, "dn: dc=planetexpress,dc=com
+    /// objectclass: dcObject
+    /// objectclass: organization
+    /// o: Planet Express
+    /// dc: planetexpress")
+    ///     .sasl_mechanism(SaslMechanism::DigestMd5)
+    ///     .sasl_mechanism(SaslMechanism::ScramSha256)
+    ///     .run().await;
+    /// server.add_sasl_user("uid=fry,dc=planetexpress,dc=com", "secret").await;
+    /// println!("ldapwhoami -Y DIGEST-MD5 -U fry -w secret -H {}", server.url());
+    /// # }
+    /// ```
+    pub fn sasl_mechanism(mut self, mechanism: SaslMechanism) -> Self {
+        if !self.sasl_mechanisms.contains(&mechanism) {
+            self.sasl_mechanisms.push(mechanism);
+        }
+        self
+    }
+
+    /// Add overlay to database 1 and load its module
+    ///
+    /// Overlay configuration is added after all LDIFs of database 0, it expects database 1
+    /// named `olcDatabase={1}mdb` as created by [`LdapServerBuilder::new`]. Note that
+    /// entries of database 1 added by builder are loaded with `slapadd`, which does not run
+    /// overlays, e.g. `memberOf` is maintained only for entries added to running server.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::{Constraint, LdapServerBuilder, Overlay};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .overlay(Overlay::member_of())
+    ///     .overlay(Overlay::ref_int(&["member"]))
+    ///     .overlay(Overlay::unique(&["uid", "mail"]))
+    ///     .overlay(Overlay::constraint("userPassword", Constraint::Count(1)))
+    ///     .run().await;
+    /// # }
+    /// ```
+    pub fn overlay(mut self, overlay: Overlay) -> Self {
+        self.overlays.push(overlay);
+        self
+    }
+
+    /// Enable password policy overlay with default policy
+    ///
+    /// Policy is stored in `cn=passwordPolicy` entry under base DN, so base DN entry has
+    /// to be added with [`LdapServerBuilder::add`]. Use [`LdapServerConn::lock_account`],
+    /// [`LdapServerConn::force_password_reset`] and [`LdapServerConn::backdate_password`]
+    /// to get accounts into states which normally take time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::{LdapServerBuilder, PasswordPolicy};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .add(1, "dn: dc=planetexpress,dc=com
+    /// objectclass: dcObject
+    /// objectclass: organization
+    /// o: Planet Express
+    /// dc: planetexpress")
+    ///     .password_policy(PasswordPolicy::new().max_failures(3))
+    ///     .run().await;
+    /// assert_eq!(
+    ///     server.password_policy_dn(),
+    ///     Some("cn=passwordPolicy,dc=planetexpress,dc=com")
+    /// );
+    /// # }
+    /// ```
+    pub fn password_policy(mut self, policy: PasswordPolicy) -> Self {
+        self.password_policy = Some(policy);
+        self
+    }
+
     /// Listen port
     pub fn port(mut self, port: u16) -> Self {
         self.port = Some(port);
@@ -97,6 +463,127 @@ impl LdapServerBuilder {
         self
     }
 
+    /// Number of times slapd is started again on new ports, when randomly picked port
+    /// turns out to be used by other process, default is 5
+    pub fn port_retries(mut self, retries: u32) -> Self {
+        self.port_retries = retries;
+        self
+    }
+
+    /// Reserve picked ports with lock files in system temporary directory, so servers
+    /// started concurrently by other processes (e.g. `cargo nextest` workers) never pick
+    /// the same port. Ports are released when [`LdapServerConn`] is dropped.
+    pub fn reserve_ports(mut self, reserve: bool) -> Self {
+        self.reserve_ports = reserve;
+        self
+    }
+
+    /// Enable slapd debug level, can be called multiple times to combine levels
+    ///
+    /// Default level is [`SlapdLogLevel::Stats`], which is required to record operations
+    /// returned by [`LdapServerConn::operations`]. slapd output is forwarded to `tracing`
+    /// with target [`crate::SLAPD_LOG_TARGET`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::{LdapServerBuilder, SlapdLogLevel};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .log_level(SlapdLogLevel::Stats)
+    ///     .log_level(SlapdLogLevel::Acl)
+    ///     .run().await;
+    /// # }
+    /// ```
+    pub fn log_level(mut self, level: SlapdLogLevel) -> Self {
+        self.log_levels.push(level);
+        self
+    }
+
+    /// Add mdb database with its own suffix and root DN, returns database number for
+    /// [`LdapServerBuilder::add`] and other `add_*` methods
+    ///
+    /// Databases are numbered after database 1 created by [`LdapServerBuilder::new`], or
+    /// after the last database of [`LdapServerBuilder::from_dir`] directory. Each database
+    /// is stored in its own directory and has indexes and ACLs of database 1, root DN must
+    /// be within `suffix`. Root DN of database 1 may manage entries of all databases, so
+    /// [`crate::LdapServerConn`] operations work for every suffix.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::LdapServerBuilder;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let mut builder = LdapServerBuilder::new("dc=planetexpress,dc=com");
+    /// let services = builder.add_database(
+    ///     "o=services",
+    ///     "cn=admin,o=services",
+    ///     "secret",
+    /// );
+    /// let server = builder
+    ///     .add(services, "dn: o=services
+    /// objectClass: organization
+    /// o: services")
+    ///     .run().await;
+    /// assert!(server.exists("o=services").await);
+    /// # }
+    /// ```
+    pub fn add_database(&mut self, suffix: &str, root_dn: &str, root_pw: &str) -> u8 {
+        let dbnum = match self.databases.last() {
+            Some(last) => last + 1,
+            None => match &self.source_dir {
+                Some(source_dir) => config::last_database(&source_dir.join("config")) + 1,
+                None => 2,
+            },
+        };
+        let ldif = DATABASE_LDIF
+            .replace("@DBNUM@", &dbnum.to_string())
+            .replace("@SUFFIX@", suffix)
+            .replace("@DBROOTDN@", root_dn)
+            .replace("@DBROOTPW@", root_pw);
+        self.includes.push((
+            0,
+            LdapFile::Text {
+                template: true,
+                content: ldif,
+            },
+        ));
+        self.databases.push(dbnum);
+        dbnum
+    }
+
+    /// Add mdb database like [`LdapServerBuilder::add_database`] and pass its number
+    /// to `f`, which may add entries to it without breaking chain of builder calls
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::LdapServerBuilder;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .with_database("o=services", "cn=admin,o=services", "secret", |builder, dbnum| {
+    ///         builder.add(dbnum, "dn: o=services
+    /// objectClass: organization
+    /// o: services")
+    ///     })
+    ///     .run().await;
+    /// assert!(server.exists("o=services").await);
+    /// # }
+    /// ```
+    pub fn with_database<F>(mut self, suffix: &str, root_dn: &str, root_pw: &str, f: F) -> Self
+    where
+        F: FnOnce(Self, u8) -> Self,
+    {
+        let dbnum = self.add_database(suffix, root_dn, root_pw);
+        f(self, dbnum)
+    }
+
     /// Add system LDIF from schema dir installed by slapd (usually in /etc/ldap/schema directory)
     ///
     /// # Examples
@@ -209,11 +696,7 @@ impl LdapServerBuilder {
         work_dir: &Path,
         config_dir: &Path,
         system_schema_dir: &Path,
-    ) {
-        fs::create_dir(&config_dir)
-            .await
-            .expect("cannot create config dir");
-
+    ) -> Result<(), LdapServerError> {
         for (idx, (dbnum, include)) in includes.into_iter().enumerate() {
             let file = match include {
                 LdapFile::SystemSchema(file) => system_schema_dir.join(file),
@@ -226,7 +709,7 @@ impl LdapServerBuilder {
                     content,
                 } => {
                     let tmp_ldif = work_dir.join(format!("tmp_{idx}.ldif"));
-                    tokio::fs::write(&tmp_ldif, content).await.unwrap();
+                    tokio::fs::write(&tmp_ldif, content).await?;
                     tmp_ldif
                 }
                 LdapFile::File { template: true, .. } | LdapFile::Text { template: true, .. } => {
@@ -234,47 +717,28 @@ impl LdapServerBuilder {
                 }
             };
 
-            LdapServerBuilder::load_ldif(config_dir, dbnum, file).await;
+            slapadd(config_dir, dbnum, file).await?;
         }
-    }
 
-    async fn load_ldif(config_dir: &Path, dbnum: u8, file: PathBuf) {
-        debug!("slapadd dbnum: {dbnum} file: {}", file.display());
-
-        let db_number = dbnum.to_string();
-        // load slapd configuration
-        let output = Command::new("slapadd")
-            .arg("-F")
-            .arg(config_dir)
-            .arg("-n")
-            .arg(db_number)
-            .arg("-l")
-            .arg(&file)
-            .output()
-            .await
-            .expect("failed to execute slapadd");
-
-        if !output.status.success() {
-            panic!(
-                "slapadd command exited with error {}, stdout: {}, stderr: {} on file {}",
-                output.status,
-                String::from_utf8_lossy(&output.stdout),
-                String::from_utf8_lossy(&output.stderr),
-                file.display()
-            );
-        }
+        Ok(())
     }
 
-    async fn build_templates(&mut self, system_schema_dir: &Path, work_dir: &Path) {
+    async fn build_templates(
+        &mut self,
+        system_schema_dir: &Path,
+        module_dir: &Path,
+        work_dir: &Path,
+    ) -> Result<(), LdapServerError> {
         let schema_dir_url = Url::from_file_path(system_schema_dir).unwrap();
         let work_dir_path = work_dir.display().to_string();
+        let module_dir_path = module_dir.display().to_string();
 
         for (_, include) in &mut self.includes {
             let content = match include {
                 LdapFile::File {
                     template: true,
                     file,
-                } => fs::read_to_string(file).await.unwrap(),
+                } => fs::read_to_string(file).await?,
                 LdapFile::Text {
                     template: true,
                     content,
@@ -285,6 +749,7 @@ impl LdapServerBuilder {
             let new_content = content
                 .replace("@SCHEMADIR@", schema_dir_url.as_ref())
                 .replace("@WORKDIR@", &work_dir_path)
+                .replace("@MODULEDIR@", &module_dir_path)
                 .replace("@BASEDN@", &self.base_dn)
                 .replace("@ROOTDN@", &self.root_dn)
                 .replace("@ROOTPW@", &self.root_pw);
@@ -294,6 +759,73 @@ impl LdapServerBuilder {
                 content: new_content,
             };
         }
+
+        Ok(())
+    }
+
+    /// Hash of resolved includes and server settings, paths to `work_dir` are ignored
+    async fn cache_key(
+        &self,
+        system_schema_dir: &Path,
+        work_dir: &Path,
+    ) -> Result<String, LdapServerError> {
+        let work_dir_path = work_dir.display().to_string();
+
+        let mut key = CacheKey::default();
+        key.update(slapd_version().await?.as_bytes());
+        key.update(self.base_dn.as_bytes());
+        key.update(self.root_dn.as_bytes());
+        key.update(self.root_pw.as_bytes());
+        for (dbnum, include) in &self.includes {
+            key.update(&[*dbnum]);
+            match include {
+                LdapFile::SystemSchema(file) => {
+                    key.update(&fs::read(system_schema_dir.join(file)).await?);
+                }
+                LdapFile::File { file, .. } => key.update(&fs::read(file).await?),
+                LdapFile::Text { content, .. } => {
+                    key.update(content.replace(&work_dir_path, "@WORKDIR@").as_bytes());
+                }
+            }
+        }
+
+        Ok(key.finish())
+    }
+
+    /// Copy server directory created by other server and read its settings
+    async fn copy_source_dir(
+        &mut self,
+        source_dir: &Path,
+        work_dir: &Path,
+        config_dir: &Path,
+    ) -> Result<(), LdapServerError> {
+        let src = source_dir.to_path_buf();
+        let dst = work_dir.to_path_buf();
+        task::spawn_blocking(move || copy_dir(&src, &dst))
+            .await
+            .unwrap()?;
+
+        // files of previous slapd process
+        for file in PROCESS_FILES {
+            let _ = fs::remove_file(work_dir.join(file)).await;
+        }
+
+        config::relocate(config_dir, work_dir).await?;
+
+        self.base_dn = database_setting(config_dir, "olcSuffix").await?;
+        self.root_dn = database_setting(config_dir, "olcRootDN").await?;
+        self.root_pw = database_setting(config_dir, "olcRootPW").await?;
+
+        if self.ssl_cert_key.is_none() {
+            if let (Ok(cert), Ok(key)) = (
+                fs::read_to_string(work_dir.join("cert.pem")).await,
+                fs::read_to_string(work_dir.join("key.pem")).await,
+            ) {
+                self.ssl_cert_key = Some((cert, key));
+            }
+        }
+
+        Ok(())
     }
 
     /// Create database and run LDAP server
@@ -309,124 +841,450 @@ impl LdapServerBuilder {
     ///     .run().await;
     /// # }
     /// ```
-    pub async fn run(mut self) -> LdapServerConn {
+    pub async fn run(self) -> LdapServerConn {
+        match self.try_run().await {
+            Ok(server) => server,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Create database and run LDAP server, returning error instead of panicking
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::{LdapServerBuilder, LdapServerError};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// match LdapServerBuilder::new("dc=planetexpress,dc=com").try_run().await {
+    ///     Ok(server) => println!("started on {}", server.url()),
+    ///     Err(LdapServerError::MissingBinary { binary, .. }) => println!("{binary} is not installed"),
+    ///     Err(e) => panic!("{e}"),
+    /// }
+    /// # }
+    /// ```
+    pub async fn try_run(mut self) -> Result<LdapServerConn, LdapServerError> {
         let schema_dir = find_slapd_schema_dir()
             .await
-            .expect("no slapd schema directory found. Is openldap server installed?");
+            .ok_or(LdapServerError::SchemaDirNotFound)?;
         let host = self
             .bind_addr
             .clone()
             .unwrap_or_else(|| "127.0.0.1".to_string());
-        let port_picker = PortPicker::new()
-            .host(host.clone())
-            .protocol(Protocol::Tcp)
-            .random(true);
-        let port = self.port.unwrap_or_else(|| {
-            port_picker.pick().unwrap_or_else(|_| {
-                let mut rng = rand::thread_rng();
-                rng.gen_range(15000..55000)
-            })
-        });
-
-        let ssl_port = self.ssl_port.unwrap_or_else(|| {
-            port_picker.pick().unwrap_or_else(|_| {
-                let mut rng = rand::thread_rng();
-                rng.gen_range(15000..55000)
-            })
-        });
+        let mut port_locks = vec![];
+        let mut pick = |taken: &[u16]| -> io::Result<u16> {
+            let (port, lock) = pick_port(&host, taken, self.reserve_ports)?;
+            port_locks.extend(lock);
+            Ok(port)
+        };
+        let port = match self.port {
+            Some(port) => port,
+            None => pick(&[self.ssl_port.unwrap_or_default()])?,
+        };
+        let ssl_port = match self.ssl_port {
+            Some(port) => port,
+            None => pick(&[port])?,
+        };
 
-        let url = format!("ldap://{host}:{port}");
-        let ssl_url = format!("ldaps://{host}:{ssl_port}");
-        let dir = tempdir().unwrap();
+        let run_dir = run_dir();
+        fs::create_dir_all(&run_dir).await?;
+        let dir = tempdir_in(&run_dir)?;
+        let config_dir = dir.path().join("config");
 
-        let (ssl_cert_pem, ssl_key_pem) = if let Some(keys) = self.ssl_cert_key.clone() {
-            keys
+        let source_dir = self.source_dir.take();
+        if let Some(source_dir) = &source_dir {
+            self.copy_source_dir(source_dir, dir.path(), &config_dir)
+                .await?;
         } else {
-            let params = if let Ok(addr) = IpAddr::from_str(&host) {
-                let mut params = CertificateParams::new(vec![]).unwrap();
-                params.subject_alt_names.push(SanType::IpAddress(addr));
-                params
-            } else {
-                CertificateParams::new(vec![host.clone()]).unwrap()
-            };
+            fs::create_dir(&config_dir).await?;
+        }
+        for dbnum in &self.databases {
+            fs::create_dir_all(dir.path().join(format!("db{dbnum}"))).await?;
+        }
+        // allows reap_orphans to find server of crashed test process
+        fs::write(
+            dir.path().join(OWNER_PID_FILE),
+            std::process::id().to_string(),
+        )
+        .await?;
 
-            let key_pair = KeyPair::generate().unwrap();
-            let cert = params.self_signed(&key_pair).unwrap();
-            let ssl_cert_pem = cert.pem();
-            let ssl_key_pem = key_pair.serialize_pem();
-            (ssl_cert_pem, ssl_key_pem)
+        let ca = match &source_dir {
+            // CA of copied server keeps its certificates valid
+            Some(_) => CertificateAuthority::load(dir.path()).await?,
+            None => None,
+        };
+        let ca = match ca {
+            Some(ca) => ca,
+            None => {
+                let ca = CertificateAuthority::generate(self.intermediate_ca)?;
+                ca.store(dir.path()).await?;
+                ca
+            }
         };
+        let ca_pem = CertificateAuthority::cert_file(dir.path());
+
+        let mut ssl_names = vec![host.clone()];
+        ssl_names.extend(DEFAULT_ALT_NAMES.iter().map(|name| name.to_string()));
+        ssl_names.extend(self.ssl_alt_names.iter().cloned());
+        let (ssl_chain_pem, ssl_key_pem) = match self.ssl_cert_key.clone() {
+            Some(keys) => keys,
+            None => ca.issue_server_cert(&ssl_names, self.ssl_preset)?,
+        };
+        let ssl_cert_pem = leaf_pem(&ssl_chain_pem).to_string();
 
         let cert_pem = dir.path().join("cert.pem");
-        fs::write(&cert_pem, &ssl_cert_pem).await.unwrap();
+        fs::write(&cert_pem, &ssl_chain_pem).await?;
 
         let key_pem = dir.path().join("key.pem");
-        fs::write(&key_pem, &ssl_key_pem).await.unwrap();
+        fs::write(&key_pem, &ssl_key_pem).await?;
 
-        self.build_templates(schema_dir, dir.path()).await;
-        let config_dir = dir.path().join("config");
-        LdapServerBuilder::build_config(self.includes, dir.path(), &config_dir, schema_dir).await;
-
-        let urls = format!("{url} {ssl_url}");
-        // launch slapd server
-        let mut server = Command::new("slapd")
-            .arg("-F")
-            .arg(&config_dir)
-            .arg("-d")
-            .arg("2048")
-            .arg("-h")
-            .arg(&urls)
-            .stderr(Stdio::piped())
-            .spawn()
-            .unwrap();
-
-        // wait until slapd server has started
-        let stderr = server.stderr.take().unwrap();
-        let mut lines = tokio::io::BufReader::new(stderr).lines();
-        let timeouted = timeout(Duration::from_secs(60), async {
-            while let Some(line) = lines.next_line().await.unwrap() {
-                debug!("slapd: {line}");
-                if line.ends_with("slapd starting") {
-                    return true;
+        let tls_required = self.start_tls == StartTls::Require;
+        let tool_cert = if tls_required && self.tls_verify_client == TlsVerifyClient::Demand {
+            // LDAP tools connect to ldaps port then and must present certificate
+            let cert = ca.issue_client_cert("cn=ldap-test-server")?;
+            let tool_cert = (
+                dir.path().join("tool-cert.pem"),
+                dir.path().join("tool-key.pem"),
+            );
+            fs::write(&tool_cert.0, cert.cert_pem()).await?;
+            fs::write(&tool_cert.1, cert.key_pem()).await?;
+            Some(tool_cert)
+        } else {
+            None
+        };
+
+        let module_dir = find_slapd_module_dir().await;
+        let password_policy = match self.password_policy.take() {
+            Some(policy) => {
+                if ppolicy::schema_required(slapd_version().await?) {
+                    // schema must be loaded before entries of other databases using it
+                    let idx = self
+                        .includes
+                        .iter()
+                        .position(|(dbnum, _)| *dbnum != 0)
+                        .unwrap_or(self.includes.len());
+                    self.includes
+                        .insert(idx, (0, LdapFile::SystemSchema("ppolicy.ldif".into())));
                 }
+                let dn = format!("cn=passwordPolicy,{}", self.base_dn);
+                self.overlays.push(Overlay::PasswordPolicy {
+                    default_policy: Some(dn.clone()),
+                });
+                Some((dn, policy))
             }
-            false
-        })
-        .await;
-
-        if timeouted.is_err() || timeouted == Ok(false) {
-            let _ = server.kill().await;
-            panic!("Failed to start slapd server: timeout");
+            None => None,
+        };
+        if !self.overlays.is_empty() {
+            let modules = overlay::modules(&self.overlays, module_dir).await;
+            let ldif = overlay::config_ldif(&self.overlays, &modules, module_dir);
+            self = self.add(0, &ldif);
         }
-
-        let timeouted = timeout(Duration::from_secs(60), async {
-            while !is_tcp_port_open(&host, port).await {
-                debug!("tcp port {port} is not open yet, waiting...");
-                sleep(Duration::from_micros(100)).await;
+        let replica = self.syncrepl.as_ref().map_or(false, |s| s.replica);
+        if replica {
+            // entries of database 1 are replicated from provider
+            self.includes.retain(|(dbnum, _)| *dbnum != 1);
+        } else if let Some((dn, policy)) = &password_policy {
+            self = self.add(1, &policy.ldif(dn));
+        }
+        self.build_templates(schema_dir, module_dir, dir.path())
+            .await?;
+        match self.cache.take().filter(|_| source_dir.is_none()) {
+            Some(cache) => {
+                let key = self.cache_key(schema_dir, dir.path()).await?;
+                if !cache.load(&key, dir.path()).await? {
+                    LdapServerBuilder::build_config(
+                        self.includes,
+                        dir.path(),
+                        &config_dir,
+                        schema_dir,
+                    )
+                    .await?;
+                    cache.store(&key, dir.path()).await?;
+                }
             }
-        })
-        .await;
+            None => {
+                LdapServerBuilder::build_config(self.includes, dir.path(), &config_dir, schema_dir)
+                    .await?;
+            }
+        }
 
-        if timeouted.is_err() {
-            let _ = server.kill().await;
-            panic!("Failed to start slapd server, port {port} not open");
+        let mut config_changes = vec![Change::Replace(
+            "olcTLSCACertificateFile",
+            ca_pem.display().to_string(),
+        )];
+        let ldapi_url = self.ldapi.then(|| {
+            // SAFETY: getuid and getgid always succeed
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            config_changes.push(Change::Append(
+                "olcAuthzRegexp",
+                format!(
+                    "\"^{}$\" \"{}\"",
+                    regex_escape(&format!(
+                        "gidNumber={gid}+uidNumber={uid},cn=peercred,cn=external,cn=auth"
+                    )),
+                    self.root_dn
+                ),
+            ));
+            ldapi_url(&dir.path().join("ldapi"))
+        });
+        if !self.sasl_mechanisms.is_empty() {
+            sasl::write_config(dir.path(), &self.sasl_mechanisms).await?;
+            // passwords are read from userPassword of entries
+            config_changes.push(Change::Replace("olcSaslAuxprops", "slapd".to_string()));
+            // allow PLAIN without TLS
+            config_changes.push(Change::Replace(
+                "olcSaslSecProps",
+                "noanonymous".to_string(),
+            ));
+            config_changes.push(Change::Append(
+                "olcAuthzRegexp",
+                sasl::user_authz_regexp(&self.base_dn),
+            ));
+        }
+        if tls_required {
+            config_changes.push(Change::Replace("olcSecurity", "tls=1".to_string()));
+        }
+        if let Some(version) = self.tls_min_version {
+            config_changes.push(Change::Replace(
+                "olcTLSProtocolMin",
+                version.protocol_min().to_string(),
+            ));
+        }
+        if let Some(cipher_suite) = &self.tls_cipher_suite {
+            config_changes.push(Change::Replace("olcTLSCipherSuite", cipher_suite.clone()));
+        }
+        if self.tls_verify_client != TlsVerifyClient::Never {
+            config_changes.push(Change::Replace(
+                "olcTLSVerifyClient",
+                self.tls_verify_client.value().to_string(),
+            ));
+        }
+        if let Some(server_id) = self.syncrepl.as_ref().and_then(|s| s.server_id) {
+            config_changes.push(Change::Replace("olcServerID", server_id.to_string()));
+        }
+        config::modify_global(&config_dir, &config_changes).await?;
+        if let Some(syncrepl) = &self.syncrepl {
+            let mut changes = vec![];
+            for (rid, provider_url) in &syncrepl.providers {
+                changes.push(Change::Append(
+                    "olcSyncrepl",
+                    replication::syncrepl_config(
+                        *rid,
+                        provider_url,
+                        &self.base_dn,
+                        &self.root_dn,
+                        &self.root_pw,
+                    ),
+                ));
+            }
+            match syncrepl.server_id {
+                Some(_) => changes.push(Change::Replace(
+                    replication::multi_provider_attribute(slapd_version().await?),
+                    "TRUE".to_string(),
+                )),
+                None => changes.extend(
+                    syncrepl
+                        .providers
+                        .first()
+                        .map(|(_, url)| Change::Replace("olcUpdateRef", url.clone())),
+                ),
+            }
+            config::modify_database(&config_dir, 1, &changes).await?;
         }
 
-        debug!("Started ldap server on {urls}");
+        let debug_level = if self.log_levels.is_empty() {
+            SlapdLogLevel::Stats.bits()
+        } else {
+            self.log_levels
+                .iter()
+                .fold(0, |acc, level| acc | level.bits())
+        };
+        // also configuration of copied server directory
+        let sasl_conf_path = dir.path().join(SASL_CONF_DIR);
+        let sasl_conf_path = fs::metadata(&sasl_conf_path)
+            .await
+            .is_ok()
+            .then_some(sasl_conf_path);
+        let mut slapd = SlapdCommand {
+            config_dir,
+            urls: String::new(),
+            debug_level,
+            host: host.clone(),
+            port,
+            sasl_conf_path,
+        };
+        let log = Arc::new(SlapdLog::default());
 
-        LdapServerConn {
+        let (mut port, mut ssl_port) = (port, ssl_port);
+        let mut retries = 0;
+        let server = loop {
+            slapd.urls = format!("ldap://{host}:{port} ldaps://{host}:{ssl_port}");
+            if let Some(ldapi_url) = &ldapi_url {
+                slapd.urls.push(' ');
+                slapd.urls.push_str(ldapi_url);
+            }
+            slapd.port = port;
+            match slapd.start(&log).await {
+                Err(LdapServerError::AddressInUse { .. })
+                    if retries < self.port_retries
+                        && (self.port.is_none() || self.ssl_port.is_none()) =>
+                {
+                    retries += 1;
+                    debug!("port {port} or {ssl_port} already in use, retry {retries}");
+                    port_locks.clear();
+                    if self.port.is_none() {
+                        let (new_port, lock) =
+                            pick_port(&host, &[port, ssl_port], self.reserve_ports)?;
+                        port = new_port;
+                        port_locks.extend(lock);
+                    }
+                    if self.ssl_port.is_none() {
+                        let (new_port, lock) =
+                            pick_port(&host, &[port, ssl_port], self.reserve_ports)?;
+                        ssl_port = new_port;
+                        port_locks.extend(lock);
+                    }
+                }
+                result => break result?,
+            }
+        };
+        let url = format!("ldap://{host}:{port}");
+        let ssl_url = format!("ldaps://{host}:{ssl_port}");
+
+        Ok(LdapServerConn {
             url,
             host,
             port,
             ssl_url,
             ssl_port,
             ssl_cert_pem,
-            dir,
+            ssl_chain_pem,
+            ssl_names,
+            password_policy_dn: password_policy.map(|(dn, _)| dn),
+            ldapi_url,
+            tls_required,
+            tool_cert,
+            ca,
+            dir: ManuallyDrop::new(dir),
             base_dn: self.base_dn,
             root_dn: self.root_dn,
             root_pw: self.root_pw,
-            server,
+            server: Some(server),
+            slapd,
+            keep_dir: self.keep_dir.unwrap_or_else(KeepDir::from_env),
+            log,
+            _port_locks: port_locks,
+        })
+    }
+
+    /// Run provider server with `syncprov` overlay and `consumers` servers replicating
+    /// its database 1
+    ///
+    /// Consumers share configuration of provider, except for entries added to database 1
+    /// and fixed ports. See [`LdapReplicaSet`] for example.
+    pub async fn run_replicated(self, consumers: usize) -> LdapReplicaSet {
+        self.try_run_replicated(consumers)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Run replicated servers, returning error instead of panicking
+    pub async fn try_run_replicated(
+        self,
+        consumers: usize,
+    ) -> Result<LdapReplicaSet, LdapServerError> {
+        let mut consumer = self.clone();
+        consumer.port = None;
+        consumer.ssl_port = None;
+
+        let mut provider = self;
+        if !provider.overlays.contains(&Overlay::SyncProv) {
+            provider.overlays.push(Overlay::SyncProv);
         }
+        let provider = provider.try_run().await?;
+        let provider_url = replication::provider_url(
+            provider.tls_required,
+            provider.host(),
+            replication::provider_port(&provider),
+        );
+
+        let mut servers = Vec::with_capacity(consumers);
+        for rid in 1..=consumers {
+            let mut consumer = consumer.clone();
+            consumer.syncrepl = Some(Syncrepl {
+                providers: vec![(rid as u16, provider_url.clone())],
+                server_id: None,
+                replica: true,
+            });
+            servers.push(consumer.try_run().await?);
+        }
+
+        Ok(LdapReplicaSet::new(provider, servers))
+    }
+
+    /// Run multi-provider cluster of `nodes` servers replicating database 1 to each other
+    ///
+    /// Entries added to database 1 are loaded by first node only and replicated to others,
+    /// other nodes do not use fixed ports. See [`LdapCluster`] for example.
+    pub async fn run_cluster(self, nodes: usize) -> LdapCluster {
+        self.try_run_cluster(nodes)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Run multi-provider cluster, returning error instead of panicking
+    pub async fn try_run_cluster(mut self, nodes: usize) -> Result<LdapCluster, LdapServerError> {
+        if !self.overlays.contains(&Overlay::SyncProv) {
+            self.overlays.push(Overlay::SyncProv);
+        }
+        let host = self
+            .bind_addr
+            .clone()
+            .unwrap_or_else(|| "127.0.0.1".to_string());
+        let tls_required = self.start_tls == StartTls::Require;
+
+        let mut links = vec![];
+        for from in 0..nodes {
+            for to in (0..nodes).filter(|to| *to != from) {
+                links.push(((from, to), LinkProxy::bind(&host).await?));
+            }
+        }
+
+        let mut servers: Vec<LdapServerConn> = Vec::with_capacity(nodes);
+        for node in 0..nodes {
+            let mut builder = self.clone();
+            if node > 0 {
+                builder.port = None;
+                builder.ssl_port = None;
+            }
+            builder.syncrepl = Some(Syncrepl {
+                providers: links
+                    .iter()
+                    .filter(|((from, _), _)| *from == node)
+                    .map(|((_, to), proxy)| {
+                        (
+                            *to as u16 + 1,
+                            replication::provider_url(tls_required, &host, proxy.port()),
+                        )
+                    })
+                    .collect(),
+                server_id: Some(node as u16 + 1),
+                replica: node > 0,
+            });
+            servers.push(builder.try_run().await?);
+        }
+
+        for ((_, to), proxy) in &mut links {
+            let server = &servers[*to];
+            proxy.start(format!(
+                "{}:{}",
+                server.host(),
+                replication::provider_port(server)
+            ));
+        }
+
+        Ok(LdapCluster::new(servers, links))
     }
 }
 
@@ -444,10 +1302,38 @@ async fn find_slapd_schema_dir() -> Option<&'static Path> {
     None
 }
 
-async fn is_tcp_port_open(host: &str, port: u16) -> bool {
-    let addr = (host, port).to_socket_addrs().unwrap().next().unwrap();
-    let Ok(sock) = timeout(Duration::from_secs(1), TcpStream::connect(&addr)).await else {
-        return false;
-    };
-    sock.is_ok()
+/// Directory with slapd modules, default Debian location when none is found
+async fn find_slapd_module_dir() -> &'static Path {
+    for dir in POSSIBLE_MODULE_DIR {
+        let dir: &Path = dir.as_ref();
+        if fs::metadata(dir).await.map(|m| m.is_dir()).unwrap_or(false) {
+            return dir;
+        }
+    }
+    POSSIBLE_MODULE_DIR[0].as_ref()
+}
+
+/// Read required attribute of database 1 from copied configuration
+async fn database_setting(config_dir: &Path, name: &str) -> io::Result<String> {
+    config::database_attribute(config_dir, 1, name)
+        .await?
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{name} not found in {}", config_dir.display()),
+            )
+        })
+}
+
+/// `ldapi://` URL of Unix domain socket, path is percent-encoded
+fn ldapi_url(socket: &Path) -> String {
+    let mut url = "ldapi://".to_string();
+    for b in socket.display().to_string().bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            url.push(b as char);
+        } else {
+            url.push_str(&format!("%{b:02X}"));
+        }
+    }
+    url
 }
@@ -0,0 +1,248 @@
+use crate::config;
+use crate::LdapServerError;
+use dircpy::copy_dir;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tokio::sync::OnceCell;
+use tokio::{fs, task};
+use tracing::debug;
+
+/// Environment variable overriding location of [`ConfigCache::default`]
+pub const CACHE_DIR_ENV: &str = "LDAP_TEST_SERVER_CACHE";
+
+/// Cache of built slapd configurations and databases
+///
+/// Built server directory is stored under key computed from included LDIFs, base DN,
+/// root DN, root password and slapd version. Next server built with the same inputs
+/// copies cached files instead of running `slapadd` for every include.
+///
+/// # Examples
+///
+/// ```
+/// use ldap_test_server::{ConfigCache, LdapServerBuilder};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let cache_dir = tempfile::tempdir().unwrap();
+/// let cache = ConfigCache::new(cache_dir.path());
+/// for _ in 0..2 {
+///     let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+///         .cache(cache.clone())
+///         .run()
+///         .await;
+/// }
+/// cache.clear().await;
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigCache {
+    dir: PathBuf,
+}
+
+impl Default for ConfigCache {
+    /// Cache in directory from `LDAP_TEST_SERVER_CACHE` environment variable,
+    /// `ldap-test-server` in system temporary directory otherwise
+    fn default() -> Self {
+        let dir = std::env::var_os(CACHE_DIR_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::temp_dir().join("ldap-test-server"));
+        Self { dir }
+    }
+}
+
+impl ConfigCache {
+    /// Cache in given directory, directory is created on first use
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Cache directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Remove all cached configurations
+    pub async fn clear(&self) {
+        self.try_clear().await.unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Remove all cached configurations, returning error instead of panicking
+    pub async fn try_clear(&self) -> Result<(), LdapServerError> {
+        match fs::remove_dir_all(&self.dir).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Move cached configurations to new directory and use it from now on
+    pub async fn relocate<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.try_relocate(dir)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Move cached configurations to new directory and use it from now on,
+    /// returning error instead of panicking
+    pub async fn try_relocate<P: Into<PathBuf>>(&mut self, dir: P) -> Result<(), LdapServerError> {
+        let dir = dir.into();
+        if fs::metadata(&self.dir).await.is_ok() {
+            if let Some(parent) = dir.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            if fs::rename(&self.dir, &dir).await.is_err() {
+                // different file system
+                let src = self.dir.clone();
+                let dst = dir.clone();
+                task::spawn_blocking(move || copy_dir(&src, &dst))
+                    .await
+                    .unwrap()?;
+                self.try_clear().await?;
+            }
+        }
+        self.dir = dir;
+        Ok(())
+    }
+
+    /// Copy cached server files to `work_dir`, returns `false` if key is not cached
+    pub(crate) async fn load(&self, key: &str, work_dir: &Path) -> Result<bool, LdapServerError> {
+        let entry = self.dir.join(key);
+        if fs::metadata(&entry).await.is_err() {
+            debug!("config cache miss: {key}");
+            return Ok(false);
+        }
+        debug!("config cache hit: {}", entry.display());
+
+        let dst = work_dir.to_path_buf();
+        task::spawn_blocking(move || copy_dir(&entry, &dst))
+            .await
+            .unwrap()?;
+        config::relocate(&work_dir.join("config"), work_dir).await?;
+
+        Ok(true)
+    }
+
+    /// Store config and databases from `work_dir` under key
+    pub(crate) async fn store(&self, key: &str, work_dir: &Path) -> Result<(), LdapServerError> {
+        fs::create_dir_all(&self.dir).await?;
+
+        // populate temporary directory first, so other processes never see partial entry
+        let tmp = self
+            .dir
+            .join(format!(".tmp-{:016x}", rand::thread_rng().gen::<u64>()));
+        let src = work_dir.to_path_buf();
+        let dst = tmp.clone();
+        task::spawn_blocking(move || copy_server_files(&src, &dst))
+            .await
+            .unwrap()?;
+
+        if let Err(e) = fs::rename(&tmp, self.dir.join(key)).await {
+            // entry stored concurrently by other server
+            debug!("config cache entry {key} not stored: {e}");
+            let _ = fs::remove_dir_all(&tmp).await;
+        }
+
+        Ok(())
+    }
+}
+
+/// Copy slapd configuration and database files, skipping certificates and runtime files
+fn copy_server_files(src: &Path, dst: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dst)?;
+    copy_dir(src.join("config"), dst.join("config"))?;
+    copy_database_files(src, dst)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        // directories of databases added by `LdapServerBuilder::add_database`
+        if name.starts_with("db") && entry.file_type()?.is_dir() {
+            std::fs::create_dir_all(dst.join(&*name))?;
+            copy_database_files(&entry.path(), &dst.join(&*name))?;
+        }
+    }
+    Ok(())
+}
+
+fn copy_database_files(src: &Path, dst: &Path) -> io::Result<()> {
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.ends_with(".mdb") && name != "lock.mdb" {
+            std::fs::copy(entry.path(), dst.join(&*name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Hash of inputs determining built configuration
+#[derive(Default)]
+pub(crate) struct CacheKey(Sha256);
+
+impl CacheKey {
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        // length prefix keeps boundaries between values
+        self.0.update((data.len() as u64).to_le_bytes());
+        self.0.update(data);
+    }
+
+    pub(crate) fn finish(self) -> String {
+        self.0
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+/// Version reported by `slapd -VV`
+pub(crate) async fn slapd_version() -> Result<&'static str, LdapServerError> {
+    static VERSION: OnceCell<String> = OnceCell::const_new();
+    VERSION
+        .get_or_try_init(|| async {
+            let output = Command::new("slapd")
+                .arg("-VV")
+                .output()
+                .await
+                .map_err(|e| LdapServerError::spawn("slapd", e))?;
+            let mut version = String::from_utf8_lossy(&output.stdout).into_owned();
+            version.push_str(&String::from_utf8_lossy(&output.stderr));
+            Ok(version)
+        })
+        .await
+        .map(String::as_str)
+}
+
+/// Major and minor version from `slapd -VV` output
+pub(crate) fn version_number(version: &str) -> Option<(u32, u32)> {
+    let (_, version) = version.split_once("slapd ")?;
+    let mut numbers = version
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|number| number.parse::<u32>().unwrap_or_default());
+    let major = numbers.next().unwrap_or_default();
+    let minor = numbers.next().unwrap_or_default();
+    Some((major, minor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_key() {
+        let key = |values: &[&str]| {
+            let mut key = CacheKey::default();
+            for value in values {
+                key.update(value.as_bytes());
+            }
+            key.finish()
+        };
+
+        assert_eq!(key(&["ab", "c"]).len(), 64);
+        assert_eq!(key(&["ab", "c"]), key(&["ab", "c"]));
+        assert_ne!(key(&["ab", "c"]), key(&["a", "bc"]));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::proxy::LinkProxy;
+use crate::replication::{context_csn, wait_until_converged};
+use crate::{LdapServerConn, LdapServerError};
+use std::time::Duration;
+
+/// Multi-provider cluster of servers replicating database 1 to each other
+///
+/// Started by [`crate::LdapServerBuilder::run_cluster`]. Each node has its own server ID,
+/// `syncprov` overlay and `olcSyncrepl` for every other node, so changes can be made on
+/// any node. Replication connections go through proxies, which can be cut to simulate
+/// network partition while client ports of nodes stay up.
+///
+/// # Examples
+///
+/// ```
+/// use ldap_test_server::LdapServerBuilder;
+/// use std::time::Duration;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let cluster = LdapServerBuilder::new("dc=planetexpress,dc=com")
+///     .add(1, "dn: dc=planetexpress,dc=com
+/// objectclass: dcObject
+/// objectclass: organization
+/// o: Planet Express
+/// dc: planetexpress")
+///     .run_cluster(2)
+///     .await;
+///
+/// cluster.cut(0, 1);
+/// cluster
+///     .node(1)
+///     .add(
+///         "dn: cn=Bender,dc=planetexpress,dc=com
+/// objectClass: person
+/// cn: Bender
+/// sn: Rodriguez",
+///     )
+///     .await;
+/// assert!(!cluster.wait_for_replication(Duration::from_secs(1)).await);
+///
+/// cluster.heal(0, 1);
+/// assert!(cluster.wait_for_replication(Duration::from_secs(10)).await);
+/// assert!(cluster.node(0).exists("cn=Bender,dc=planetexpress,dc=com").await);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LdapCluster {
+    nodes: Vec<LdapServerConn>,
+    /// Proxy of replication connections by consumer and provider node
+    links: Vec<((usize, usize), LinkProxy)>,
+}
+
+impl LdapCluster {
+    pub(crate) fn new(nodes: Vec<LdapServerConn>, links: Vec<((usize, usize), LinkProxy)>) -> Self {
+        Self { nodes, links }
+    }
+
+    /// All nodes, index of node is its server ID minus one
+    pub fn nodes(&self) -> &[LdapServerConn] {
+        &self.nodes
+    }
+
+    /// Node by index, panics when index is out of range
+    pub fn node(&self, index: usize) -> &LdapServerConn {
+        &self.nodes[index]
+    }
+
+    /// Node by index, e.g. to stop or restart it
+    pub fn node_mut(&mut self, index: usize) -> &mut LdapServerConn {
+        &mut self.nodes[index]
+    }
+
+    /// Stop replication between two nodes in both directions
+    ///
+    /// Open replication connections are closed and new ones are closed right after
+    /// connecting until [`LdapCluster::heal`].
+    pub fn cut(&self, a: usize, b: usize) {
+        for proxy in self.link(a, b) {
+            proxy.cut();
+        }
+    }
+
+    /// Resume replication between two nodes, consumers reconnect within a second
+    pub fn heal(&self, a: usize, b: usize) {
+        for proxy in self.link(a, b) {
+            proxy.heal();
+        }
+    }
+
+    /// Stop replication between node and all other nodes
+    pub fn isolate(&self, node: usize) {
+        for other in (0..self.nodes.len()).filter(|other| *other != node) {
+            self.cut(node, other);
+        }
+    }
+
+    /// Resume replication between all nodes
+    pub fn heal_all(&self) {
+        for (_, proxy) in &self.links {
+            proxy.heal();
+        }
+    }
+
+    /// Check if replication between two nodes was cut
+    pub fn is_cut(&self, a: usize, b: usize) -> bool {
+        self.link(a, b).any(LinkProxy::is_cut)
+    }
+
+    /// Wait until `contextCSN` of base DN entry is the same on all running nodes,
+    /// returns `false` on timeout
+    pub async fn wait_for_replication(&self, timeout: Duration) -> bool {
+        self.try_wait_for_replication(timeout)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Wait until replication converges, returning error instead of panicking
+    pub async fn try_wait_for_replication(
+        &self,
+        timeout: Duration,
+    ) -> Result<bool, LdapServerError> {
+        wait_until_converged(timeout, || self.converged()).await
+    }
+
+    async fn converged(&self) -> Result<bool, LdapServerError> {
+        let mut expected = None;
+        for node in self.nodes.iter().filter(|node| node.is_running()) {
+            let csn = context_csn(node).await?;
+            match &expected {
+                None => expected = Some(csn),
+                Some(expected) if *expected != csn => return Ok(false),
+                Some(_) => {}
+            }
+        }
+        Ok(true)
+    }
+
+    /// Proxies of both directions between two nodes, panics when there is no such link
+    fn link(&self, a: usize, b: usize) -> impl Iterator<Item = &LinkProxy> {
+        assert!(
+            a != b && a < self.nodes.len() && b < self.nodes.len(),
+            "no replication link between nodes {a} and {b}"
+        );
+        self.links
+            .iter()
+            .filter(move |((from, to), _)| (*from, *to) == (a, b) || (*from, *to) == (b, a))
+            .map(|(_, proxy)| proxy)
+    }
+}
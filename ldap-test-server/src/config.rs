@@ -0,0 +1,359 @@
+use crate::search::unfold;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::{fs, task};
+
+/// Location of `olcDatabase={N}...` entry in slapd config directory
+async fn database_file(config_dir: &Path, dbnum: u8) -> io::Result<PathBuf> {
+    let prefix = format!("olcDatabase={{{dbnum}}}");
+    let mut entries = fs::read_dir(config_dir.join("cn=config")).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(&prefix) && name.ends_with(".ldif") {
+            return Ok(entry.path());
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("database {dbnum} not found in {}", config_dir.display()),
+    ))
+}
+
+/// Number of the last `olcDatabase={N}...` entry in slapd config directory, 1 when
+/// directory cannot be read
+pub(crate) fn last_database(config_dir: &Path) -> u8 {
+    let Ok(entries) = std::fs::read_dir(config_dir.join("cn=config")) else {
+        return 1;
+    };
+    entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name();
+            let (dbnum, _) = name
+                .to_str()?
+                .strip_prefix("olcDatabase={")?
+                .split_once('}')?;
+            dbnum.parse().ok()
+        })
+        .max()
+        .unwrap_or(1)
+}
+
+/// Read first value of attribute from slapd config LDIF file
+pub(crate) fn attribute_value(ldif: &str, attribute: &str) -> Option<String> {
+    unfold(ldif).into_iter().find_map(|line| {
+        let (name, value) = split_attribute(&line)?;
+        name.eq_ignore_ascii_case(attribute).then_some(value)
+    })
+}
+
+/// Name and value of unfolded `name: value` line, base64 value of `name:: value` is decoded
+fn split_attribute(line: &str) -> Option<(&str, String)> {
+    let (name, value) = line.split_once(':')?;
+    let value = match value.strip_prefix(':') {
+        Some(encoded) => String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?,
+        None => value.trim_start().to_string(),
+    };
+    Some((name, value))
+}
+
+/// Read first value of attribute from `olcDatabase={N}...` entry
+pub(crate) async fn database_attribute(
+    config_dir: &Path,
+    dbnum: u8,
+    attribute: &str,
+) -> io::Result<Option<String>> {
+    let file = database_file(config_dir, dbnum).await?;
+    let ldif = fs::read_to_string(&file).await?;
+    Ok(attribute_value(&ldif, attribute))
+}
+
+/// Rewrite paths pointing to old server directory (database directory, pid file,
+/// TLS certificates) to `new_dir`, so copied server can be started from new location
+pub(crate) async fn relocate(config_dir: &Path, new_dir: &Path) -> io::Result<()> {
+    let global = fs::read_to_string(config_dir.join("cn=config.ldif")).await?;
+    let old_dir = match attribute_value(&global, "olcPidFile") {
+        Some(pid_file) => Path::new(&pid_file)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+        None => database_directory(config_dir, 1).await?,
+    };
+
+    if old_dir.as_os_str().is_empty() || old_dir == new_dir {
+        return Ok(());
+    }
+
+    let config_dir = config_dir.to_path_buf();
+    let old_dir = old_dir.display().to_string();
+    let new_dir = new_dir.display().to_string();
+    task::spawn_blocking(move || relocate_dir(&config_dir, &old_dir, &new_dir))
+        .await
+        .unwrap()
+}
+
+fn relocate_dir(dir: &Path, old_dir: &str, new_dir: &str) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            relocate_dir(&path, old_dir, new_dir)?;
+        } else if path.extension().map_or(false, |ext| ext == "ldif") {
+            let ldif = std::fs::read_to_string(&path)?;
+            if let Some(new_ldif) = relocate_ldif(&ldif, old_dir, new_dir) {
+                std::fs::write(&path, new_ldif)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replace `old_dir` prefix of attribute values, returns `None` when nothing changed
+fn relocate_ldif(ldif: &str, old_dir: &str, new_dir: &str) -> Option<String> {
+    let mut changed = false;
+    let mut ret = String::with_capacity(ldif.len());
+    for line in unfold(ldif) {
+        // checksum would not match after modification
+        if line.starts_with("# CRC32 ") {
+            continue;
+        }
+
+        let relocated = line.split_once(": ").and_then(|(name, value)| {
+            let rest = value.strip_prefix(old_dir)?;
+            if !rest.is_empty() && !rest.starts_with('/') {
+                return None;
+            }
+            Some(format!("{name}: {new_dir}{rest}"))
+        });
+
+        match relocated {
+            Some(line) => {
+                changed = true;
+                ret.push_str(&line);
+            }
+            None => ret.push_str(&line),
+        }
+        ret.push('\n');
+    }
+
+    changed.then_some(ret)
+}
+
+/// Change of attribute in slapd config entry
+#[derive(Debug, Clone)]
+pub(crate) enum Change {
+    /// Add value of ordered attribute (e.g. `olcAuthzRegexp`) after existing values,
+    /// value is skipped when already present
+    Append(&'static str, String),
+    /// Replace all values of attribute
+    Replace(&'static str, String),
+}
+
+/// Modify global `cn=config` entry of stopped server
+pub(crate) async fn modify_global(config_dir: &Path, changes: &[Change]) -> io::Result<()> {
+    let file = config_dir.join("cn=config.ldif");
+    let ldif = fs::read_to_string(&file).await?;
+    if let Some(new_ldif) = modify_entry(&ldif, changes) {
+        fs::write(&file, new_ldif).await?;
+    }
+    Ok(())
+}
+
+/// Modify `olcDatabase={N}...` entry of stopped server
+pub(crate) async fn modify_database(
+    config_dir: &Path,
+    dbnum: u8,
+    changes: &[Change],
+) -> io::Result<()> {
+    let file = database_file(config_dir, dbnum).await?;
+    let ldif = fs::read_to_string(&file).await?;
+    if let Some(new_ldif) = modify_entry(&ldif, changes) {
+        fs::write(&file, new_ldif).await?;
+    }
+    Ok(())
+}
+
+/// Apply changes to LDIF of single entry, returns `None` when nothing changed
+fn modify_entry(ldif: &str, changes: &[Change]) -> Option<String> {
+    let mut lines: Vec<String> = unfold(ldif)
+        .into_iter()
+        // checksum would not match after modification
+        .filter(|line| !line.starts_with("# CRC32 "))
+        .collect();
+    while lines.last().map_or(false, |line| line.is_empty()) {
+        lines.pop();
+    }
+    let values = |lines: &[String], attribute: &str| -> Vec<String> {
+        lines
+            .iter()
+            .filter_map(|line| {
+                let (name, value) = split_attribute(line)?;
+                name.eq_ignore_ascii_case(attribute).then_some(value)
+            })
+            .collect()
+    };
+
+    let mut changed = false;
+    for change in changes {
+        match change {
+            Change::Append(attribute, value) => {
+                let existing = values(&lines, attribute);
+                if existing
+                    .iter()
+                    .any(|existing| strip_index(existing) == value)
+                {
+                    continue;
+                }
+                lines.push(format!("{attribute}: {{{}}}{value}", existing.len()));
+            }
+            Change::Replace(attribute, value) => {
+                if values(&lines, attribute) == [value.as_str()] {
+                    continue;
+                }
+                lines.retain(|line| {
+                    line.split_once(':')
+                        .map_or(true, |(name, _)| !name.eq_ignore_ascii_case(attribute))
+                });
+                lines.push(format!("{attribute}: {value}"));
+            }
+        }
+        changed = true;
+    }
+
+    changed.then(|| {
+        let mut ret = lines.join("\n");
+        ret.push('\n');
+        ret
+    })
+}
+
+/// Value without `{N}` index of ordered attribute
+fn strip_index(value: &str) -> &str {
+    value
+        .strip_prefix('{')
+        .and_then(|rest| rest.split_once('}'))
+        .filter(|(index, _)| index.chars().all(|c| c.is_ascii_digit()))
+        .map_or(value, |(_, value)| value)
+}
+
+/// Directory with files of database
+pub(crate) async fn database_directory(config_dir: &Path, dbnum: u8) -> io::Result<PathBuf> {
+    let file = database_file(config_dir, dbnum).await?;
+    let ldif = fs::read_to_string(&file).await?;
+    attribute_value(&ldif, "olcDbDirectory")
+        .map(PathBuf::from)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("olcDbDirectory not found in {}", file.display()),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relocate_paths() {
+        let ldif = "# AUTO-GENERATED FILE - DO NOT EDIT!! Use ldapmodify.
+# CRC32 1a2b3c4d
+dn: cn=config
+objectClass: olcGlobal
+cn: config
+olcPidFile: /tmp/.tmpAbCdEf/slapd.pid
+olcTLSCertificateFile: /tmp/.tmpAbCd
+ Ef/cert.pem
+olcTLSCACertificateFile: /tmp/.tmpAbCdEfGh/ca.pem
+";
+
+        let relocated = relocate_ldif(ldif, "/tmp/.tmpAbCdEf", "/tmp/.tmpXyz").unwrap();
+        assert!(!relocated.contains("CRC32"));
+        assert_eq!(
+            attribute_value(&relocated, "olcPidFile").as_deref(),
+            Some("/tmp/.tmpXyz/slapd.pid")
+        );
+        assert_eq!(
+            attribute_value(&relocated, "olcTLSCertificateFile").as_deref(),
+            Some("/tmp/.tmpXyz/cert.pem")
+        );
+        assert_eq!(
+            attribute_value(&relocated, "olcTLSCACertificateFile").as_deref(),
+            Some("/tmp/.tmpAbCdEfGh/ca.pem")
+        );
+
+        assert_eq!(relocate_ldif(ldif, "/var/lib/ldap", "/tmp/.tmpXyz"), None);
+    }
+
+    #[test]
+    fn modify_global_entry() {
+        let ldif = "# AUTO-GENERATED FILE - DO NOT EDIT!! Use ldapmodify.
+# CRC32 1a2b3c4d
+dn: cn=config
+objectClass: olcGlobal
+cn: config
+olcAuthzRegexp: {0}\"uid=([^,]*),cn=plain,cn=auth\" \"cn=$1,dc=planetexpress,dc=com\"
+olcSecurity: ssf=1
+entryCSN: 20241111120000.000000Z#000000#000#000000
+
+";
+        let regexp = "\"gidNumber=0+uidNumber=0,cn=peercred,cn=external,cn=auth\" \"cn=admin\"";
+        let changes = [
+            Change::Append("olcAuthzRegexp", regexp.to_string()),
+            Change::Replace("olcSecurity", "tls=1".to_string()),
+        ];
+        let changed = modify_entry(ldif, &changes).unwrap();
+        assert!(!changed.contains("CRC32"));
+        assert!(changed.contains(&format!("\nolcAuthzRegexp: {{1}}{regexp}\n")));
+        assert!(!changed.contains("ssf=1"));
+        assert!(changed.ends_with("\nolcSecurity: tls=1\n"));
+
+        assert_eq!(modify_entry(&changed, &changes), None);
+    }
+
+    #[test]
+    fn modify_base64_values() {
+        // slapd writes values with special characters base64 encoded
+        let ldif = "dn: cn=config
+objectClass: olcGlobal
+cn: config
+olcAuthzRegexp:: ezB9ImNuPSguKikiICJjbj0kMSxkYz1wbGFuZXRleHByZXNzLGRjPWNvbSI=
+olcSecurity:: c3NmPTE=
+";
+        let changes = [
+            Change::Append(
+                "olcAuthzRegexp",
+                "\"cn=(.*)\" \"cn=$1,dc=planetexpress,dc=com\"".to_string(),
+            ),
+            Change::Replace("olcSecurity", "ssf=1".to_string()),
+        ];
+        assert_eq!(modify_entry(ldif, &changes), None);
+
+        let changed =
+            modify_entry(ldif, &[Change::Replace("olcSecurity", "tls=1".to_string())]).unwrap();
+        assert!(!changed.contains("olcSecurity::"));
+        assert!(changed.ends_with("\nolcSecurity: tls=1\n"));
+    }
+
+    #[test]
+    fn last_database_number() {
+        let config_dir = tempfile::tempdir().unwrap();
+        assert_eq!(last_database(config_dir.path()), 1);
+
+        let dir = config_dir.path().join("cn=config");
+        std::fs::create_dir(&dir).unwrap();
+        for name in [
+            "olcDatabase={-1}frontend.ldif",
+            "olcDatabase={0}config.ldif",
+            "olcDatabase={1}mdb.ldif",
+            "olcDatabase={3}mdb.ldif",
+            "olcDatabase={2}mdb.ldif",
+            "cn=schema.ldif",
+        ] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        assert_eq!(last_database(config_dir.path()), 3);
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+/// Error returned by fallible operations of [`crate::LdapServerBuilder`] and [`crate::LdapServerConn`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LdapServerError {
+    /// Required OpenLDAP command is not installed (not found in $PATH)
+    MissingBinary {
+        /// Name of the command
+        binary: String,
+        /// Error returned while spawning the command
+        source: io::Error,
+    },
+    /// None of known slapd schema directories exists
+    SchemaDirNotFound,
+    /// `slapadd` failed to load LDIF file into database
+    Slapadd {
+        /// Exit status of slapadd
+        status: ExitStatus,
+        /// Standard output of slapadd
+        stdout: String,
+        /// Standard error of slapadd
+        stderr: String,
+        /// Loaded LDIF file
+        file: PathBuf,
+    },
+    /// slapd has not reported start in expected time
+    StartupTimeout {
+        /// Last lines of slapd output
+        log: Vec<String>,
+    },
+    /// slapd process exited before it was ready to accept connections
+    ServerExited {
+        /// Exit status of slapd
+        status: ExitStatus,
+        /// Last lines of slapd output
+        log: Vec<String>,
+    },
+    /// slapd could not listen on port, because it is used by other process
+    AddressInUse {
+        /// Last lines of slapd output
+        log: Vec<String>,
+    },
+    /// slapd started, but TCP port is not open
+    PortNotOpen {
+        /// TCP port number
+        port: u16,
+        /// Last lines of slapd output
+        log: Vec<String>,
+    },
+    /// LDAP tool (`ldapadd`, `ldapmodify`, `ldapdelete`, `ldapsearch`, `slapcat`) exited with error
+    LdapTool {
+        /// Name of the command
+        command: String,
+        /// Exit status of the command
+        status: ExitStatus,
+        /// Standard output of the command
+        stdout: String,
+        /// Standard error of the command
+        stderr: String,
+        /// Applied LDIF file
+        file: Option<PathBuf>,
+    },
+    /// Operation requires running slapd, but server was stopped with [`crate::LdapServerConn::stop`]
+    NotRunning,
+    /// DN is not supported by operation, e.g. as certificate subject
+    InvalidDn(String),
+    /// SSL certificate generation failed
+    Certificate(rcgen::Error),
+    /// I/O error
+    Io(io::Error),
+}
+
+impl LdapServerError {
+    pub(crate) fn spawn(binary: &str, source: io::Error) -> Self {
+        if source.kind() == io::ErrorKind::NotFound {
+            LdapServerError::MissingBinary {
+                binary: binary.to_string(),
+                source,
+            }
+        } else {
+            LdapServerError::Io(source)
+        }
+    }
+}
+
+impl fmt::Display for LdapServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LdapServerError::MissingBinary { binary, source } => write!(
+                f,
+                "failed to execute {binary}: {source}. Is openldap server installed?"
+            ),
+            LdapServerError::SchemaDirNotFound => write!(
+                f,
+                "no slapd schema directory found. Is openldap server installed?"
+            ),
+            LdapServerError::Slapadd {
+                status,
+                stdout,
+                stderr,
+                file,
+            } => write!(
+                f,
+                "slapadd command exited with error {status}, stdout: {stdout}, stderr: {stderr} on file {}",
+                file.display()
+            ),
+            LdapServerError::StartupTimeout { log } => {
+                write!(f, "Failed to start slapd server: timeout")?;
+                write_log(f, log)
+            }
+            LdapServerError::ServerExited { status, log } => {
+                write!(f, "Failed to start slapd server: slapd exited with {status}")?;
+                write_log(f, log)
+            }
+            LdapServerError::AddressInUse { log } => {
+                write!(f, "Failed to start slapd server: address already in use")?;
+                write_log(f, log)
+            }
+            LdapServerError::PortNotOpen { port, log } => {
+                write!(f, "Failed to start slapd server, port {port} not open")?;
+                write_log(f, log)
+            }
+            LdapServerError::LdapTool {
+                command,
+                status,
+                stdout,
+                stderr,
+                file,
+            } => {
+                write!(
+                    f,
+                    "{command} command exited with error {status}, stdout: {stdout}, stderr: {stderr}"
+                )?;
+                if let Some(file) = file {
+                    write!(f, " on file {}", file.display())?;
+                }
+                Ok(())
+            }
+            LdapServerError::NotRunning => write!(f, "slapd server is not running"),
+            LdapServerError::InvalidDn(dn) => write!(f, "unsupported DN: {dn}"),
+            LdapServerError::Certificate(e) => write!(f, "failed to generate certificate: {e}"),
+            LdapServerError::Io(e) => write!(f, "I/O error: {e}"),
+        }
+    }
+}
+
+fn write_log(f: &mut fmt::Formatter<'_>, log: &[String]) -> fmt::Result {
+    if log.is_empty() {
+        return Ok(());
+    }
+
+    write!(f, ", slapd log:")?;
+    for line in log {
+        write!(f, "\n  {line}")?;
+    }
+    Ok(())
+}
+
+impl std::error::Error for LdapServerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LdapServerError::MissingBinary { source, .. } => Some(source),
+            LdapServerError::Certificate(e) => Some(e),
+            LdapServerError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LdapServerError {
+    fn from(e: io::Error) -> Self {
+        LdapServerError::Io(e)
+    }
+}
+
+impl From<rcgen::Error> for LdapServerError {
+    fn from(e: rcgen::Error) -> Self {
+        LdapServerError::Certificate(e)
+    }
+}
@@ -0,0 +1,40 @@
+/// Environment variable setting default of [`crate::LdapServerBuilder::keep_dir`]
+///
+/// `always` (or `1`, `true`) keeps every server directory, `panic` (or `on-panic`) keeps
+/// directories of servers dropped while thread is panicking.
+pub const KEEP_DIR_ENV: &str = "LDAP_TEST_SERVER_KEEP";
+
+/// When server directory is kept after [`crate::LdapServerConn`] is dropped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeepDir {
+    /// Always remove server directory
+    #[default]
+    Never,
+    /// Keep server directory when server is dropped during panic, e.g. by failing test
+    OnPanic,
+    /// Always keep server directory
+    Always,
+}
+
+impl KeepDir {
+    /// Value of `LDAP_TEST_SERVER_KEEP` environment variable, [`KeepDir::Never`] if not set
+    pub fn from_env() -> Self {
+        let Some(value) = std::env::var_os(KEEP_DIR_ENV) else {
+            return KeepDir::Never;
+        };
+        match value.to_string_lossy().to_ascii_lowercase().as_str() {
+            "always" | "1" | "true" | "yes" => KeepDir::Always,
+            "panic" | "on-panic" | "on_panic" | "onpanic" => KeepDir::OnPanic,
+            _ => KeepDir::Never,
+        }
+    }
+
+    /// Check if directory should be kept now
+    pub(crate) fn keep(self) -> bool {
+        match self {
+            KeepDir::Never => false,
+            KeepDir::OnPanic => std::thread::panicking(),
+            KeepDir::Always => true,
+        }
+    }
+}
@@ -39,16 +39,58 @@
 #![warn(missing_docs)]
 use dircpy::copy_dir;
 use std::convert::AsRef;
-use std::path::Path;
-use tempfile::TempDir;
+use std::io::Write;
+use std::mem::ManuallyDrop;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tempfile::{TempDir, TempPath};
 use tokio::process::{Child, Command};
 use tokio::task;
 use tracing::{debug, warn};
 
 mod builder;
+mod cache;
+mod cluster;
+mod config;
+mod error;
+mod keep;
+mod log;
+mod operations;
+mod overlay;
+mod pool;
+mod ports;
+mod ppolicy;
+mod proxy;
+mod replication;
+mod sasl;
+mod search;
+mod slapd;
+mod tls;
 
 pub use builder::LdapServerBuilder;
-
+pub use cache::{ConfigCache, CACHE_DIR_ENV};
+pub use cluster::LdapCluster;
+pub use error::LdapServerError;
+pub use keep::{KeepDir, KEEP_DIR_ENV};
+#[cfg(feature = "macros")]
+pub use ldap_test_server_macros::ldap_test;
+use log::SlapdLog;
+pub use log::{SlapdLogLevel, SLAPD_LOG_TARGET};
+pub use operations::{Operation, OperationKind, Scope};
+pub use overlay::{Constraint, Overlay};
+pub use pool::{shared_server, try_shared_server, LdapLease, LdapServerPool};
+pub use ppolicy::PasswordPolicy;
+pub use replication::LdapReplicaSet;
+pub use sasl::SaslMechanism;
+pub use search::Entry;
+use slapd::SlapdCommand;
+pub use slapd::{is_installed, reap_orphans, try_reap_orphans};
+use tls::CertificateAuthority;
+pub use tls::{
+    CertificatePreset, ClientCertificate, StartTls, TlsSession, TlsVerifyClient, TlsVersion,
+};
 /// Connection to running LDAP server
 #[derive(Debug)]
 pub struct LdapServerConn {
@@ -58,12 +100,39 @@ pub struct LdapServerConn {
     ssl_url: String,
     ssl_port: u16,
     ssl_cert_pem: String,
-    #[allow(unused)]
-    dir: TempDir,
+    ssl_chain_pem: String,
+    /// Host names and addresses of generated server certificate
+    ssl_names: Vec<String>,
+    password_policy_dn: Option<String>,
+    ldapi_url: Option<String>,
+    tls_required: bool,
+    /// Certificate and key presented by LDAP tools, when server demands client certificate
+    tool_cert: Option<(PathBuf, PathBuf)>,
+    ca: CertificateAuthority,
+    /// Not dropped when directory is kept, see [`KeepDir`]
+    dir: ManuallyDrop<TempDir>,
     base_dn: String,
     root_dn: String,
     root_pw: String,
-    server: Child,
+    server: Option<Child>,
+    slapd: SlapdCommand,
+    keep_dir: KeepDir,
+    log: Arc<SlapdLog>,
+    /// Released when server is dropped
+    _port_locks: Vec<ports::PortLock>,
+}
+
+/// Content of database 1 captured by [`LdapServerConn::snapshot`]
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    ldif: String,
+}
+
+impl Snapshot {
+    /// Database content as LDIF (output of slapcat)
+    pub fn ldif(&self) -> &str {
+        &self.ldif
+    }
 }
 
 impl LdapServerConn {
@@ -93,10 +162,40 @@ impl LdapServerConn {
     }
 
     /// PEM Certificate for ssl port
+    ///
+    /// Generated certificate is signed by test CA, clients should trust
+    /// [`LdapServerConn::ca_cert_pem`] instead.
     pub fn ssl_cert_pem(&self) -> &str {
         &self.ssl_cert_pem
     }
 
+    /// PEM certificate chain sent by server, server certificate followed
+    /// by intermediate CA certificate
+    pub fn chain_pem(&self) -> &str {
+        &self.ssl_chain_pem
+    }
+
+    /// PEM certificate of intermediate CA, if enabled with [`LdapServerBuilder::intermediate_ca`]
+    pub fn intermediate_cert_pem(&self) -> Option<&str> {
+        self.ca.intermediate_cert_pem()
+    }
+
+    /// PEM certificate of root test CA, which signs server certificate (directly or through
+    /// intermediate CA) and certificates issued by [`LdapServerConn::issue_client_cert`]
+    pub fn ca_cert_pem(&self) -> &str {
+        self.ca.cert_pem()
+    }
+
+    /// URL (schema=ldapi) of Unix domain socket, if enabled with [`LdapServerBuilder::ldapi`]
+    pub fn ldapi_url(&self) -> Option<&str> {
+        self.ldapi_url.as_deref()
+    }
+
+    /// DN of default password policy, if enabled with [`LdapServerBuilder::password_policy`]
+    pub fn password_policy_dn(&self) -> Option<&str> {
+        self.password_policy_dn.as_deref()
+    }
+
     /// Base DN of this LDAP server
     pub fn base_dn(&self) -> &str {
         &self.base_dn
@@ -117,15 +216,349 @@ impl LdapServerConn {
         self.dir.path()
     }
 
+    /// Last lines written by slapd to its standard error
+    pub fn log_tail(&self) -> Vec<String> {
+        self.log.tail()
+    }
+
+    /// Operations received by this LDAP server, parsed from slapd stats log
+    ///
+    /// Requires [`SlapdLogLevel::Stats`] log level, which is enabled by default.
+    /// Operations sent by [`LdapServerConn::add`] and similar methods are recorded as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ldap_test_server::{LdapServerBuilder, OperationKind};
+    /// #
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// # let server = LdapServerBuilder::new("dc=planetexpress,dc=com").run().await;
+    /// server.add("dn: dc=planetexpress,dc=com
+    /// objectclass: dcObject
+    /// objectclass: organization
+    /// o: Planet Express
+    /// dc: planetexpress").await;
+    ///
+    /// let adds = server
+    ///     .operations()
+    ///     .into_iter()
+    ///     .filter(|o| o.kind == OperationKind::Add)
+    ///     .count();
+    /// assert_eq!(adds, 1);
+    /// # }
+    /// ```
+    pub fn operations(&self) -> Vec<Operation> {
+        self.log.operations.operations()
+    }
+
+    /// Wait until operation matching predicate is received by this LDAP server
+    ///
+    /// Returns `None` when no matching operation is recorded within `timeout`.
+    /// Predicate is checked again when server sends operation result.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ldap_test_server::{LdapServerBuilder, OperationKind};
+    /// # use std::time::Duration;
+    /// #
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// # let server = LdapServerBuilder::new("dc=planetexpress,dc=com").run().await;
+    /// # server.add("dn: dc=planetexpress,dc=com
+    /// # objectclass: dcObject
+    /// # objectclass: organization
+    /// # o: Planet Express
+    /// # dc: planetexpress").await;
+    /// let bind = server
+    ///     .wait_for_operation(
+    ///         |o| o.kind == OperationKind::Bind && o.result == Some(0),
+    ///         Duration::from_secs(5),
+    ///     )
+    ///     .await
+    ///     .expect("no bind");
+    /// assert_eq!(bind.dn.as_deref(), Some(server.root_dn()));
+    /// # }
+    /// ```
+    pub async fn wait_for_operation<F>(&self, predicate: F, timeout: Duration) -> Option<Operation>
+    where
+        F: Fn(&Operation) -> bool,
+    {
+        self.log.operations.wait_for(predicate, timeout).await
+    }
+
+    /// TLS sessions established by clients (on `ldaps://` port or with StartTLS),
+    /// parsed from slapd stats log
+    pub fn tls_sessions(&self) -> Vec<TlsSession> {
+        self.log.operations.tls_sessions()
+    }
+
+    /// TLS session of connection, `None` if connection did not negotiate TLS
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ldap_test_server::{LdapServerBuilder, OperationKind};
+    /// # use std::time::Duration;
+    /// #
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// # let server = LdapServerBuilder::new("dc=planetexpress,dc=com").run().await;
+    /// # server.add("dn: dc=planetexpress,dc=com
+    /// # objectclass: dcObject
+    /// # objectclass: organization
+    /// # o: Planet Express
+    /// # dc: planetexpress").await;
+    /// let bind = server
+    ///     .wait_for_operation(|o| o.kind == OperationKind::Bind, Duration::from_secs(5))
+    ///     .await
+    ///     .unwrap();
+    /// // ldapadd run by LdapServerConn::add does not use TLS
+    /// assert!(server.tls_session(bind.conn).is_none());
+    /// # }
+    /// ```
+    pub fn tls_session(&self, conn: u64) -> Option<TlsSession> {
+        self.tls_sessions().into_iter().find(|s| s.conn == conn)
+    }
+
+    /// Wait until TLS session is established on connection, or on any connection
+    /// if `conn` is `None`
+    pub async fn wait_for_tls(&self, conn: Option<u64>, timeout: Duration) -> Option<TlsSession> {
+        self.log.operations.wait_for_tls(conn, timeout).await
+    }
+
+    /// Forget all operations and TLS sessions recorded so far
+    ///
+    /// Recorded operations are also forgotten when slapd is started again, as connection
+    /// numbers of new process start from the beginning.
+    pub fn clear_operations(&self) {
+        self.log.operations.clear()
+    }
+
+    /// Stop slapd gracefully (SIGTERM) and return its exit status
+    ///
+    /// Server directory with configuration and data is kept, so server can be started
+    /// again with [`LdapServerConn::start`] on the same ports.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ldap_test_server::LdapServerBuilder;
+    /// #
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let mut server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .add(1, "dn: dc=planetexpress,dc=com
+    /// objectclass: dcObject
+    /// objectclass: organization
+    /// o: Planet Express
+    /// dc: planetexpress")
+    ///     .run().await;
+    ///
+    /// let status = server.stop().await;
+    /// assert!(status.success());
+    /// assert!(!server.is_running());
+    ///
+    /// server.start().await;
+    /// assert!(server.exists("dc=planetexpress,dc=com").await);
+    /// # }
+    /// ```
+    pub async fn stop(&mut self) -> ExitStatus {
+        self.try_stop().await.unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Stop slapd gracefully, returning error instead of panicking
+    pub async fn try_stop(&mut self) -> Result<ExitStatus, LdapServerError> {
+        let mut server = self.server.take().ok_or(LdapServerError::NotRunning)?;
+        let status = slapd::stop(&mut server).await?;
+        debug!("stopped slapd server: {status}");
+        Ok(status)
+    }
+
+    /// Start stopped slapd again from the same server directory on the same ports
+    ///
+    /// Does nothing if server is already running.
+    pub async fn start(&mut self) -> &Self {
+        self.try_start().await.unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Start stopped slapd again, returning error instead of panicking
+    pub async fn try_start(&mut self) -> Result<&Self, LdapServerError> {
+        if self.server.is_none() {
+            self.server = Some(self.slapd.start(&self.log).await?);
+        }
+        Ok(self)
+    }
+
+    /// Stop and start slapd, clients have to reconnect
+    pub async fn restart(&mut self) -> &Self {
+        self.try_restart().await.unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Stop and start slapd, returning error instead of panicking
+    pub async fn try_restart(&mut self) -> Result<&Self, LdapServerError> {
+        if self.server.is_some() {
+            self.try_stop().await?;
+        }
+        self.try_start().await
+    }
+
+    /// Check if slapd was not stopped with [`LdapServerConn::stop`]
+    pub fn is_running(&self) -> bool {
+        self.server.is_some()
+    }
+
+    /// Replace server certificate and key with new ones signed by test CA
+    ///
+    /// Certificate files are overwritten and running slapd reloads them, as `cn=config`
+    /// TLS settings are modified, so existing connections keep old certificate and new
+    /// connections get the new one. It requires access to `cn=config` granted to root DN
+    /// by default configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::{CertificatePreset, LdapServerBuilder};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let mut server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .ssl_certificate_preset(CertificatePreset::Expired)
+    ///     .run().await;
+    /// let expired = server.ssl_cert_pem().to_string();
+    /// server.rotate_certificate().await;
+    /// assert_ne!(server.ssl_cert_pem(), expired);
+    /// # }
+    /// ```
+    pub async fn rotate_certificate(&mut self) -> &Self {
+        self.try_rotate_certificate()
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Replace server certificate and key, returning error instead of panicking
+    pub async fn try_rotate_certificate(&mut self) -> Result<&Self, LdapServerError> {
+        let (chain, key) = self
+            .ca
+            .issue_server_cert(&self.ssl_names, CertificatePreset::Valid)?;
+        let cert_file = self.dir.path().join("cert.pem");
+        let key_file = self.dir.path().join("key.pem");
+        tokio::fs::write(&cert_file, &chain).await?;
+        tokio::fs::write(&key_file, &key).await?;
+
+        if self.is_running() {
+            // any modification of TLS settings makes slapd reload certificate files
+            self.try_modify(&format!(
+                "dn: cn=config
+changetype: modify
+replace: olcTLSCertificateFile
+olcTLSCertificateFile: {}
+-
+replace: olcTLSCertificateKeyFile
+olcTLSCertificateKeyFile: {}
+",
+                cert_file.display(),
+                key_file.display()
+            ))
+            .await?;
+        }
+
+        self.ssl_cert_pem = tls::leaf_pem(&chain).to_string();
+        self.ssl_chain_pem = chain;
+        Ok(self)
+    }
+
+    /// Capture content of database 1
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ldap_test_server::LdapServerBuilder;
+    /// #
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let mut server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .add(1, "dn: dc=planetexpress,dc=com
+    /// objectclass: dcObject
+    /// objectclass: organization
+    /// o: Planet Express
+    /// dc: planetexpress")
+    ///     .run().await;
+    ///
+    /// let snapshot = server.snapshot().await;
+    /// server.add("dn: ou=people,dc=planetexpress,dc=com
+    /// objectClass: organizationalUnit
+    /// ou: people").await;
+    ///
+    /// server.restore(&snapshot).await;
+    /// assert!(!server.exists("ou=people,dc=planetexpress,dc=com").await);
+    /// # }
+    /// ```
+    pub async fn snapshot(&self) -> Snapshot {
+        self.try_snapshot().await.unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Capture content of database 1, returning error instead of panicking
+    pub async fn try_snapshot(&self) -> Result<Snapshot, LdapServerError> {
+        let ldif = slapd::slapcat(&self.slapd.config_dir, 1).await?;
+        Ok(Snapshot { ldif })
+    }
+
+    /// Roll back database 1 to snapshot
+    ///
+    /// slapd is stopped for the time of reloading database and started again
+    /// on the same ports with the same certificate, so clients have to reconnect.
+    /// Operations recorded before restore are forgotten.
+    pub async fn restore(&mut self, snapshot: &Snapshot) -> &Self {
+        self.try_restore(snapshot)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Roll back database 1 to snapshot, returning error instead of panicking
+    pub async fn try_restore(&mut self, snapshot: &Snapshot) -> Result<&Self, LdapServerError> {
+        let config_dir = self.slapd.config_dir.clone();
+        let db_dir = config::database_directory(&config_dir, 1).await?;
+        let snapshot_ldif = self.dir.path().join("snapshot.ldif");
+        tokio::fs::write(&snapshot_ldif, &snapshot.ldif).await?;
+
+        if self.server.is_some() {
+            self.try_stop().await?;
+        }
+        // also when reloading fails, operations of rolled back database are not valid
+        self.log.new_process();
+
+        for file in ["data.mdb", "lock.mdb"] {
+            match tokio::fs::remove_file(db_dir.join(file)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        slapd::slapadd(&config_dir, 1, snapshot_ldif).await?;
+
+        self.try_start().await
+    }
+
     /// Clone LDAP server files to new location
     pub async fn clone_to_dir<P: AsRef<Path>>(&self, desc: P) {
+        self.try_clone_to_dir(desc)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Clone LDAP server files to new location, returning error instead of panicking
+    pub async fn try_clone_to_dir<P: AsRef<Path>>(&self, desc: P) -> Result<(), LdapServerError> {
         let src = self.dir.path().to_path_buf();
         let dst = desc.as_ref().to_path_buf();
-        task::spawn_blocking(move || {
-            copy_dir(&src, &dst).unwrap();
-        })
-        .await
-        .unwrap();
+        task::spawn_blocking(move || copy_dir(&src, &dst))
+            .await
+            .unwrap()?;
+        // copy is not owned by this process, it must not be removed by reap_orphans
+        for file in slapd::PROCESS_FILES {
+            let _ = tokio::fs::remove_file(desc.as_ref().join(file)).await;
+        }
+        Ok(())
     }
 
     /// Apply LDIF from text
@@ -156,15 +589,27 @@ impl LdapServerConn {
     /// # }
     /// ```
     pub async fn add(&self, ldif_text: &str) -> &Self {
-        let tmp_ldif = self.dir.path().join("tmp.ldif");
-        tokio::fs::write(&tmp_ldif, ldif_text).await.unwrap();
-        self.add_file(tmp_ldif).await
+        self.try_add(ldif_text)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Apply LDIF from text, returning error instead of panicking
+    pub async fn try_add(&self, ldif_text: &str) -> Result<&Self, LdapServerError> {
+        let tmp_ldif = self.tmp_ldif(ldif_text)?;
+        self.try_add_file(&tmp_ldif).await
     }
 
     /// Apply LDIF from file
     pub async fn add_file<P: AsRef<Path>>(&self, file: P) -> &Self {
-        self.load_ldif_file("ldapadd", file, self.root_dn(), self.root_pw())
+        self.try_add_file(file)
             .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Apply LDIF from file, returning error instead of panicking
+    pub async fn try_add_file<P: AsRef<Path>>(&self, file: P) -> Result<&Self, LdapServerError> {
+        self.load_ldif_file("ldapadd", file).await
     }
 
     /// Apply modification LDIF from text
@@ -200,15 +645,27 @@ impl LdapServerConn {
     /// # }
     /// ```
     pub async fn modify(&self, ldif_text: &str) -> &Self {
-        let tmp_ldif = self.dir.path().join("tmp.ldif");
-        tokio::fs::write(&tmp_ldif, ldif_text).await.unwrap();
-        self.modify_file(tmp_ldif).await
+        self.try_modify(ldif_text)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Apply modification LDIF from text, returning error instead of panicking
+    pub async fn try_modify(&self, ldif_text: &str) -> Result<&Self, LdapServerError> {
+        let tmp_ldif = self.tmp_ldif(ldif_text)?;
+        self.try_modify_file(&tmp_ldif).await
     }
 
     /// Apply modification LDIF from file
     pub async fn modify_file<P: AsRef<Path>>(&self, file: P) -> &Self {
-        self.load_ldif_file("ldapmodify", file, self.root_dn(), self.root_pw())
+        self.try_modify_file(file)
             .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Apply modification LDIF from file, returning error instead of panicking
+    pub async fn try_modify_file<P: AsRef<Path>>(&self, file: P) -> Result<&Self, LdapServerError> {
+        self.load_ldif_file("ldapmodify", file).await
     }
 
     /// Apply deletion LDIF from text
@@ -242,61 +699,476 @@ impl LdapServerConn {
     /// # }
     /// ```
     pub async fn delete(&self, ldif_text: &str) -> &Self {
-        let tmp_ldif = self.dir.path().join("tmp.ldif");
-        tokio::fs::write(&tmp_ldif, ldif_text).await.unwrap();
-        self.modify_file(tmp_ldif).await
+        self.try_delete(ldif_text)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Apply deletion LDIF from text, returning error instead of panicking
+    pub async fn try_delete(&self, ldif_text: &str) -> Result<&Self, LdapServerError> {
+        let tmp_ldif = self.tmp_ldif(ldif_text)?;
+        self.try_modify_file(&tmp_ldif).await
     }
 
     /// Apply deletion LDIF from file
     pub async fn delete_file<P: AsRef<Path>>(&self, file: P) -> &Self {
-        self.load_ldif_file("ldapdelete", file, self.root_dn(), self.root_pw())
+        self.try_delete_file(file)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Apply deletion LDIF from file, returning error instead of panicking
+    pub async fn try_delete_file<P: AsRef<Path>>(&self, file: P) -> Result<&Self, LdapServerError> {
+        self.load_ldif_file("ldapdelete", file).await
+    }
+
+    /// Search entries, bound as root DN
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ldap_test_server::{LdapServerBuilder, Scope};
+    /// #
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .add(1, "dn: dc=planetexpress,dc=com
+    /// objectclass: dcObject
+    /// objectclass: organization
+    /// o: Planet Express
+    /// dc: planetexpress")
+    ///     .run().await;
+    ///
+    /// let entries = server
+    ///     .search(server.base_dn(), Scope::Base, "(objectClass=*)", &["o"])
+    ///     .await;
+    /// assert_eq!(entries[0].attr_str("o"), Some("Planet Express"));
+    /// # }
+    /// ```
+    pub async fn search(
+        &self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: &[&str],
+    ) -> Vec<Entry> {
+        self.try_search(base, scope, filter, attrs)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Search entries, returning error instead of panicking
+    pub async fn try_search(
+        &self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: &[&str],
+    ) -> Result<Vec<Entry>, LdapServerError> {
+        let scope = match scope {
+            Scope::Base => "base",
+            Scope::OneLevel => "one",
+            Scope::Subtree => "sub",
+            Scope::Children => "children",
+        };
+
+        let output = self
+            .ldap_tool("ldapsearch")
+            .args([
+                "-LLL",
+                "-o",
+                "ldif-wrap=no",
+                "-b",
+                base,
+                "-s",
+                scope,
+                filter,
+            ])
+            .args(attrs)
+            .output()
+            .await
+            .map_err(|e| LdapServerError::spawn("ldapsearch", e))?;
+
+        // noSuchObject, base entry does not exist
+        if output.status.code() == Some(32) {
+            return Ok(vec![]);
+        }
+
+        if !output.status.success() {
+            return Err(LdapServerError::LdapTool {
+                command: "ldapsearch".to_string(),
+                status: output.status,
+                stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                file: None,
+            });
+        }
+
+        Ok(Entry::parse_ldif(&String::from_utf8_lossy(&output.stdout))?)
+    }
+
+    /// Read entry by DN
+    pub async fn get(&self, dn: &str) -> Option<Entry> {
+        self.try_get(dn).await.unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Read entry by DN, returning error instead of panicking
+    pub async fn try_get(&self, dn: &str) -> Result<Option<Entry>, LdapServerError> {
+        let entries = self
+            .try_search(dn, Scope::Base, "(objectClass=*)", &[])
+            .await?;
+        Ok(entries.into_iter().next())
+    }
+
+    /// Check if entry exists
+    pub async fn exists(&self, dn: &str) -> bool {
+        self.try_exists(dn).await.unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Check if entry exists, returning error instead of panicking
+    pub async fn try_exists(&self, dn: &str) -> Result<bool, LdapServerError> {
+        let entries = self
+            .try_search(dn, Scope::Base, "(objectClass=*)", &["1.1"])
+            .await?;
+        Ok(!entries.is_empty())
+    }
+
+    /// Count entries matching filter in whole base DN subtree
+    pub async fn count(&self, filter: &str) -> usize {
+        self.try_count(filter)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Count entries matching filter in whole base DN subtree, returning error instead of panicking
+    pub async fn try_count(&self, filter: &str) -> Result<usize, LdapServerError> {
+        let entries = self
+            .try_search(self.base_dn(), Scope::Subtree, filter, &["1.1"])
+            .await?;
+        Ok(entries.len())
+    }
+
+    /// Issue client certificate signed by test CA and map its subject to entry with
+    /// the same DN, for SASL EXTERNAL binds over TLS
+    ///
+    /// Subject DN may contain `cn`, `uid`, `ou`, `o`, `dc`, `l`, `st` and `c` attributes.
+    /// Server requests client certificates only with [`LdapServerBuilder::tls_verify_client`].
+    /// Mapping is added to `olcAuthzRegexp` of running server, so it requires access to
+    /// `cn=config` granted to root DN by default configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::{LdapServerBuilder, TlsVerifyClient};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .tls_verify_client(TlsVerifyClient::Demand)
+    ///     .run().await;
+    /// let client = server
+    ///     .issue_client_cert("cn=Philip J. Fry,dc=planetexpress,dc=com")
+    ///     .await;
+    /// assert_eq!(client.subject_dn(), "cn=Philip J. Fry,dc=planetexpress,dc=com");
+    /// # }
+    /// ```
+    pub async fn issue_client_cert(&self, subject_dn: &str) -> ClientCertificate {
+        self.try_issue_client_cert(subject_dn)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Issue client certificate, returning error instead of panicking
+    pub async fn try_issue_client_cert(
+        &self,
+        subject_dn: &str,
+    ) -> Result<ClientCertificate, LdapServerError> {
+        let cert = self.ca.issue_client_cert(subject_dn)?;
+        self.try_modify(&format!(
+            "dn: cn=config\nchangetype: modify\nadd: olcAuthzRegexp\nolcAuthzRegexp: {}\n",
+            tls::subject_authz_regexp(subject_dn)?
+        ))
+        .await?;
+        Ok(cert)
+    }
+
+    /// Add user, which can bind with SASL mechanisms enabled by
+    /// [`LdapServerBuilder::sasl_mechanism`]
+    ///
+    /// First RDN of `dn` has to be `uid`, its value is SASL user name. Entry has
+    /// `account` and `simpleSecurityObject` object classes and clear text `userPassword`
+    /// required by challenge-response mechanisms.
+    pub async fn add_sasl_user(&self, dn: &str, password: &str) -> &Self {
+        self.try_add_sasl_user(dn, password)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Add SASL user, returning error instead of panicking
+    pub async fn try_add_sasl_user(
+        &self,
+        dn: &str,
+        password: &str,
+    ) -> Result<&Self, LdapServerError> {
+        self.try_add(&sasl::user_ldif(dn, password)?).await
+    }
+
+    /// Lock account, binds fail until it is unlocked with [`LdapServerConn::unlock_account`]
+    ///
+    /// Requires password policy enabled with [`LdapServerBuilder::password_policy`].
+    pub async fn lock_account(&self, dn: &str) -> &Self {
+        self.try_lock_account(dn)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Lock account, returning error instead of panicking
+    pub async fn try_lock_account(&self, dn: &str) -> Result<&Self, LdapServerError> {
+        self.try_modify_relax(&format!(
+            "dn: {dn}\nchangetype: modify\nreplace: pwdAccountLockedTime\npwdAccountLockedTime: {}\n",
+            ppolicy::LOCKED_PERMANENTLY
+        ))
+        .await
+    }
+
+    /// Unlock account locked by failed binds or [`LdapServerConn::lock_account`]
+    /// and reset its failure count
+    pub async fn unlock_account(&self, dn: &str) -> &Self {
+        self.try_unlock_account(dn)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Unlock account, returning error instead of panicking
+    pub async fn try_unlock_account(&self, dn: &str) -> Result<&Self, LdapServerError> {
+        // replace without values does not fail when attribute is missing
+        self.try_modify_relax(&format!(
+            "dn: {dn}\nchangetype: modify\nreplace: pwdAccountLockedTime\n-\nreplace: pwdFailureTime\n"
+        ))
+        .await
+    }
+
+    /// Mark password as reset by administrator (`pwdReset`), user has to change it
+    /// when policy has [`PasswordPolicy::must_change`] set
+    pub async fn force_password_reset(&self, dn: &str) -> &Self {
+        self.try_force_password_reset(dn)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Mark password as reset, returning error instead of panicking
+    pub async fn try_force_password_reset(&self, dn: &str) -> Result<&Self, LdapServerError> {
+        self.try_modify_relax(&format!(
+            "dn: {dn}\nchangetype: modify\nreplace: pwdReset\npwdReset: TRUE\n"
+        ))
+        .await
+    }
+
+    /// Pretend password was changed `age` ago (`pwdChangedTime`), e.g. to make it expired
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ldap_test_server::{LdapServerBuilder, PasswordPolicy};
+    /// use std::time::Duration;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+    ///     .add(1, "dn: dc=planetexpress,dc=com
+    /// objectclass: dcObject
+    /// objectclass: organization
+    /// o: Planet Express
+    /// dc: planetexpress")
+    ///     .password_policy(PasswordPolicy::new().max_age(Duration::from_secs(3600)))
+    ///     .run().await;
+    /// server.add("dn: cn=Philip J. Fry,dc=planetexpress,dc=com
+    /// objectClass: person
+    /// cn: Philip J. Fry
+    /// sn: Fry
+    /// userPassword: slurm").await;
+    /// server
+    ///     .backdate_password("cn=Philip J. Fry,dc=planetexpress,dc=com", Duration::from_secs(7200))
+    ///     .await;
+    /// # }
+    /// ```
+    pub async fn backdate_password(&self, dn: &str, age: Duration) -> &Self {
+        self.try_backdate_password(dn, age)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Backdate password change, returning error instead of panicking
+    pub async fn try_backdate_password(
+        &self,
+        dn: &str,
+        age: Duration,
+    ) -> Result<&Self, LdapServerError> {
+        let changed = SystemTime::now()
+            .checked_sub(age)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        self.try_modify_relax(&format!(
+            "dn: {dn}\nchangetype: modify\nreplace: pwdChangedTime\npwdChangedTime: {}\n",
+            ppolicy::generalized_time(changed)
+        ))
+        .await
+    }
+
+    /// Apply modification with Relax control, which allows changing operational attributes
+    async fn try_modify_relax(&self, ldif_text: &str) -> Result<&Self, LdapServerError> {
+        let tmp_ldif = self.tmp_ldif(ldif_text)?;
+        self.load_ldif_file_with("ldapmodify", &["-e", "relax"], &tmp_ldif)
             .await
     }
 
+    /// Delete entry with all its children
+    pub(crate) async fn try_delete_tree(&self, dn: &str) -> Result<&Self, LdapServerError> {
+        let output = self
+            .ldap_tool("ldapdelete")
+            .arg("-r")
+            .arg(dn)
+            .output()
+            .await
+            .map_err(|e| LdapServerError::spawn("ldapdelete", e))?;
+
+        if !output.status.success() {
+            return Err(LdapServerError::LdapTool {
+                command: "ldapdelete".to_string(),
+                status: output.status,
+                stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                file: None,
+            });
+        }
+
+        Ok(self)
+    }
+
+    /// Write LDIF to uniquely named file in server directory, so concurrent
+    /// operations do not overwrite each other's input. File is removed when returned
+    /// path is dropped, keep it until LDAP tool exits.
+    fn tmp_ldif(&self, ldif_text: &str) -> Result<TempPath, LdapServerError> {
+        let mut file = tempfile::Builder::new()
+            .prefix("tmp")
+            .suffix(".ldif")
+            .tempfile_in(self.dir.path())?;
+        file.write_all(ldif_text.as_bytes())?;
+        Ok(file.into_temp_path())
+    }
+
+    /// Command of LDAP client tool bound as root DN
+    fn ldap_tool(&self, command: &str) -> Command {
+        let mut cmd = Command::new(command);
+        // olcSecurity tls=1 rejects ldapi connections too, as they have no TLS
+        if let Some(ldapi_url) = self.ldapi_url().filter(|_| !self.tls_required) {
+            // peer credentials are mapped to root DN
+            cmd.args(["-Y", "EXTERNAL", "-Q", "-H", ldapi_url]);
+            return cmd;
+        }
+
+        let url = if self.tls_required {
+            // server certificate is not verified, as it is ours
+            cmd.env("LDAPTLS_REQCERT", "never");
+            if let Some((cert, key)) = &self.tool_cert {
+                cmd.env("LDAPTLS_CERT", cert).env("LDAPTLS_KEY", key);
+            }
+            self.ssl_url()
+        } else {
+            self.url()
+        };
+        cmd.args(["-x", "-D", self.root_dn(), "-w", self.root_pw(), "-H", url]);
+        cmd
+    }
+
     async fn load_ldif_file<P: AsRef<Path>>(
         &self,
         command: &str,
         file: P,
-        binddn: &str,
-        password: &str,
-    ) -> &Self {
+    ) -> Result<&Self, LdapServerError> {
+        self.load_ldif_file_with(command, &[], file).await
+    }
+
+    async fn load_ldif_file_with<P: AsRef<Path>>(
+        &self,
+        command: &str,
+        args: &[&str],
+        file: P,
+    ) -> Result<&Self, LdapServerError> {
         let file = file.as_ref();
 
-        let output = Command::new(command)
-            .args(["-x", "-D", binddn, "-w", password, "-H", self.url(), "-f"])
+        let output = self
+            .ldap_tool(command)
+            .args(args)
+            .arg("-f")
             .arg(file)
             .output()
             .await
-            .expect("failed to load ldap file");
+            .map_err(|e| LdapServerError::spawn(command, e))?;
 
         if !output.status.success() {
-            panic!(
-                "{command} command exited with error {}, stdout: {}, stderr: {} on file {}",
-                output.status,
-                String::from_utf8_lossy(&output.stdout),
-                String::from_utf8_lossy(&output.stderr),
-                file.display()
-            );
+            return Err(LdapServerError::LdapTool {
+                command: command.to_string(),
+                status: output.status,
+                stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                file: Some(file.to_path_buf()),
+            });
         }
 
-        self
+        Ok(self)
     }
 }
 
 impl Drop for LdapServerConn {
     fn drop(&mut self) {
-        if let Err(e) = self.server.start_kill() {
-            warn!(
-                "failed to kill slapd server: {}, pid: {:?}",
-                e,
-                self.server.id()
-            );
+        self.kill();
+
+        if self.keep_dir.keep() {
+            self.log_kept_dir();
         } else {
-            debug!("killed slapd server pid: {:?}", self.server.id());
+            // SAFETY: dir is not used after drop
+            unsafe { ManuallyDrop::drop(&mut self.dir) };
         }
     }
 }
 
+impl LdapServerConn {
+    fn kill(&mut self) {
+        let Some(mut server) = self.server.take() else {
+            return;
+        };
+        let pid = server.id();
+        if let Err(e) = server.start_kill() {
+            warn!("failed to kill slapd server: {}, pid: {:?}", e, pid);
+            return;
+        }
+
+        // drop must not block runtime of async test, killed slapd is reaped by background
+        // runtime, which outlives runtime of test
+        pool::runtime().spawn(async move {
+            match server.wait().await {
+                Ok(_) => debug!("killed slapd server pid: {:?}", pid),
+                Err(e) => warn!("failed to wait for slapd server: {}, pid: {:?}", e, pid),
+            }
+        });
+    }
+
+    fn log_kept_dir(&self) {
+        // kept directory must not be removed by reap_orphans
+        let _ = std::fs::remove_file(self.dir.path().join(slapd::OWNER_PID_FILE));
+
+        warn!(
+            "kept slapd server directory {}\n  {}\n  ldapsearch -x -H \"{}\" -D \"{}\" -w \"{}\" -b \"{}\" \"(objectClass=*)\"",
+            self.dir.path().display(),
+            self.slapd.command_line(),
+            self.url,
+            self.root_dn,
+            self.root_pw,
+            self.base_dn,
+        );
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -325,6 +1197,14 @@ ou: people",
             .add_file(1, concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fry.ldif"))
             .run()
             .await;
+        let ldif_files = |dir: &Path| {
+            std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .filter(|name| name.to_string_lossy().ends_with(".ldif"))
+                .count()
+        };
+        let ldif_files_before = ldif_files(server.server_dir());
 
         server
             .add(
@@ -342,7 +1222,255 @@ ou: Intern
 uid: amy",
             )
             .await;
+        // LDIF file of operation is removed
+        assert_eq!(ldif_files(server.server_dir()), ldif_files_before);
 
         println!("Server started in {} ms", started.elapsed().as_millis());
     }
+
+    #[tokio::test]
+    async fn try_run_invalid_ldif() {
+        let result = LdapServerBuilder::new("dc=planetexpress,dc=com")
+            .add(
+                1,
+                "dn: dc=planetexpress,dc=com\nobjectclass: notExistingClass",
+            )
+            .try_run()
+            .await;
+
+        assert!(
+            matches!(result, Err(LdapServerError::Slapadd { .. })),
+            "expected slapadd error, got {result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn search_entries() {
+        let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+            .add(
+                1,
+                "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress
+
+dn: ou=people,dc=planetexpress,dc=com
+objectClass: top
+objectClass: organizationalUnit
+description: Planet Express crew
+ou: people",
+            )
+            .add_file(1, concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fry.ldif"))
+            .run()
+            .await;
+
+        let fry = "cn=Philip J. Fry,ou=people,dc=planetexpress,dc=com";
+        let entry = server.get(fry).await.expect("fry entry");
+        assert_eq!(entry.dn, fry);
+        assert_eq!(entry.attr_str("givenName"), Some("Philip"));
+
+        assert!(server.exists("ou=people,dc=planetexpress,dc=com").await);
+        assert!(!server.exists("ou=robots,dc=planetexpress,dc=com").await);
+        assert_eq!(server.count("(objectClass=inetOrgPerson)").await, 1);
+
+        let entries = server
+            .search(
+                "ou=people,dc=planetexpress,dc=com",
+                Scope::OneLevel,
+                "(sn=Fry)",
+                &["cn"],
+            )
+            .await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].attrs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn run_from_dir() {
+        let golden = tempfile::tempdir().unwrap();
+        let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+            .add(
+                1,
+                "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress",
+            )
+            .run()
+            .await;
+        server.clone_to_dir(golden.path()).await;
+
+        let cloned = LdapServerBuilder::from_dir(golden.path())
+            .add(
+                1,
+                "dn: ou=people,dc=planetexpress,dc=com
+objectClass: top
+objectClass: organizationalUnit
+ou: people",
+            )
+            .run()
+            .await;
+
+        assert_ne!(cloned.port(), server.port());
+        assert_eq!(cloned.base_dn(), server.base_dn());
+        assert_eq!(cloned.root_dn(), server.root_dn());
+        assert_eq!(cloned.root_pw(), server.root_pw());
+        assert!(cloned.exists("ou=people,dc=planetexpress,dc=com").await);
+        assert!(!server.exists("ou=people,dc=planetexpress,dc=com").await);
+    }
+
+    #[tokio::test]
+    async fn run_cached() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = ConfigCache::new(cache_dir.path().join("cache"));
+        let builder = || {
+            LdapServerBuilder::new("dc=planetexpress,dc=com")
+                .cache(cache.clone())
+                .add(
+                    1,
+                    "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress
+
+dn: ou=people,dc=planetexpress,dc=com
+objectClass: top
+objectClass: organizationalUnit
+ou: people",
+                )
+                .add_file(1, concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fry.ldif"))
+        };
+
+        let first = builder().run().await;
+        let second = builder().run().await;
+        let fry = "cn=Philip J. Fry,ou=people,dc=planetexpress,dc=com";
+        assert!(first.exists(fry).await);
+        assert!(second.exists(fry).await);
+        assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 1);
+
+        let mut cache = cache;
+        cache.relocate(cache_dir.path().join("moved")).await;
+        assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 1);
+        cache.clear().await;
+        assert!(!cache.dir().exists());
+    }
+
+    #[tokio::test]
+    async fn operations_after_restart() {
+        let mut server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+            .add(
+                1,
+                "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress",
+            )
+            .run()
+            .await;
+        server.count("(o=Planet Express)").await;
+        assert!(server
+            .wait_for_operation(
+                |o| o.filter.as_deref() == Some("(o=Planet Express)") && o.result.is_some(),
+                Duration::from_secs(5),
+            )
+            .await
+            .is_some());
+
+        server.restart().await;
+        assert!(server.operations().is_empty());
+        server.count("(dc=planetexpress)").await;
+        let search = server
+            .wait_for_operation(
+                |o| o.kind == OperationKind::Search && o.result.is_some(),
+                Duration::from_secs(5),
+            )
+            .await
+            .expect("search after restart");
+        assert_eq!(search.filter.as_deref(), Some("(dc=planetexpress)"));
+    }
+
+    #[tokio::test]
+    async fn operations_after_restore() {
+        let mut server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+            .add(
+                1,
+                "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress",
+            )
+            .run()
+            .await;
+        let snapshot = server.snapshot().await;
+        server
+            .add(
+                "dn: ou=people,dc=planetexpress,dc=com
+objectClass: organizationalUnit
+ou: people",
+            )
+            .await;
+        assert!(server
+            .wait_for_operation(
+                |o| o.kind == OperationKind::Add && o.result.is_some(),
+                Duration::from_secs(5),
+            )
+            .await
+            .is_some());
+
+        server.restore(&snapshot).await;
+        assert!(server.operations().is_empty());
+        assert!(!server.exists("ou=people,dc=planetexpress,dc=com").await);
+        assert!(server
+            .wait_for_operation(|o| o.kind == OperationKind::Add, Duration::from_secs(1))
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn keep_dir() {
+        let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+            .keep_dir(KeepDir::Always)
+            .run()
+            .await;
+        let dir = server.server_dir().to_path_buf();
+        drop(server);
+        assert!(dir.join("config").is_dir());
+        assert!(!dir.join(slapd::OWNER_PID_FILE).exists());
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+            .keep_dir(KeepDir::OnPanic)
+            .run()
+            .await;
+        let dir = server.server_dir().to_path_buf();
+        drop(server);
+        assert!(!dir.exists());
+    }
+
+    #[tokio::test]
+    async fn port_in_use() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let result = LdapServerBuilder::new("dc=planetexpress,dc=com")
+            .port(port)
+            .try_run()
+            .await;
+        assert!(
+            matches!(result, Err(LdapServerError::AddressInUse { .. })),
+            "expected address in use error, got {result:?}"
+        );
+
+        let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+            .reserve_ports(true)
+            .run()
+            .await;
+        assert_ne!(server.port(), port);
+        assert_ne!(server.port(), server.ssl_port());
+    }
 }
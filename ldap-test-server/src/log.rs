@@ -0,0 +1,276 @@
+use crate::operations::OperationRecorder;
+use crate::pool::runtime;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{BufReader, Lines};
+use tokio::process::ChildStderr;
+use tracing::debug;
+
+/// Number of last slapd output lines kept for diagnostics
+const LOG_TAIL_LINES: usize = 50;
+
+/// `tracing` target of events created from slapd output
+pub const SLAPD_LOG_TARGET: &str = "ldap_test_server::slapd";
+
+/// slapd debug level (see `loglevel` in slapd-config(5))
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SlapdLogLevel {
+    /// Trace function calls
+    Trace,
+    /// Debug packet handling
+    Packets,
+    /// Heavy trace debugging
+    Args,
+    /// Connection management
+    Conns,
+    /// Print out packets sent and received
+    Ber,
+    /// Search filter processing
+    Filter,
+    /// Configuration file processing
+    Config,
+    /// Access control list processing
+    Acl,
+    /// Stats log connections/operations/results
+    Stats,
+    /// Stats log entries sent
+    Stats2,
+    /// Print communication with shell backends
+    Shell,
+    /// Entry parsing
+    Parse,
+    /// Syncrepl consumer processing
+    Sync,
+    /// Only messages that get logged whatever log level is set
+    None,
+    /// Enable all debugging
+    Any,
+}
+
+impl SlapdLogLevel {
+    pub(crate) fn bits(self) -> i32 {
+        match self {
+            SlapdLogLevel::Trace => 1,
+            SlapdLogLevel::Packets => 2,
+            SlapdLogLevel::Args => 4,
+            SlapdLogLevel::Conns => 8,
+            SlapdLogLevel::Ber => 16,
+            SlapdLogLevel::Filter => 32,
+            SlapdLogLevel::Config => 64,
+            SlapdLogLevel::Acl => 128,
+            SlapdLogLevel::Stats => 256,
+            SlapdLogLevel::Stats2 => 512,
+            SlapdLogLevel::Shell => 1024,
+            SlapdLogLevel::Parse => 2048,
+            SlapdLogLevel::Sync => 16384,
+            SlapdLogLevel::None => 32768,
+            SlapdLogLevel::Any => -1,
+        }
+    }
+}
+
+/// Parsed line of slapd output
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct LogLine<'a> {
+    pub(crate) conn: Option<u64>,
+    pub(crate) op: Option<u64>,
+    pub(crate) op_type: Option<&'a str>,
+    /// Line without timestamp and thread id prefix
+    pub(crate) message: &'a str,
+}
+
+impl<'a> LogLine<'a> {
+    pub(crate) fn parse(line: &'a str) -> Self {
+        // "<timestamp> <thread id> message" or plain message, connection is the first
+        // token of message, "conn=" elsewhere may be part of e.g. search filter
+        let message = strip_prefix(line);
+        let mut tokens = message.split(' ');
+        let conn = tokens
+            .next()
+            .and_then(|t| t.strip_prefix("conn="))
+            .and_then(|t| t.parse().ok());
+        let mut op = None;
+        let mut op_type = None;
+        if conn.is_some() {
+            match tokens.next() {
+                Some(t) if t.starts_with("op=") => {
+                    op = t[3..].parse().ok();
+                    op_type = tokens.next();
+                }
+                // e.g. "conn=1000 fd=12 ACCEPT from ..."
+                Some(t) if t.starts_with("fd=") => op_type = tokens.next(),
+                _ => {}
+            }
+        }
+
+        LogLine {
+            conn,
+            op,
+            op_type,
+            message,
+        }
+    }
+}
+
+fn strip_prefix(line: &str) -> &str {
+    let mut parts = line.splitn(3, ' ');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(ts), Some(thread), Some(rest)) if is_timestamp(ts) && thread.starts_with("0x") => {
+            rest
+        }
+        _ => line,
+    }
+}
+
+fn is_timestamp(token: &str) -> bool {
+    !token.is_empty() && token.chars().all(|c| c.is_ascii_hexdigit() || c == '.')
+}
+
+/// Last lines written by slapd to stderr
+#[derive(Debug, Default)]
+pub(crate) struct SlapdLog {
+    tail: Mutex<VecDeque<String>>,
+    pub(crate) operations: OperationRecorder,
+    /// Number of started slapd processes, operations are recorded for the last one
+    generation: AtomicU64,
+}
+
+impl SlapdLog {
+    /// Forget operations of previous slapd process, new process numbers connections
+    /// from conn=1000 again. Returns generation of new process.
+    pub(crate) fn new_process(&self) -> u64 {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.operations.clear();
+        generation
+    }
+
+    /// Forward line of current slapd process to `tracing` and keep it in tail
+    pub(crate) fn record(&self, line: String) {
+        self.record_of(self.generation.load(Ordering::SeqCst), line)
+    }
+
+    /// Forward line to `tracing` and keep it in tail, operations of previous
+    /// processes are not recorded
+    fn record_of(&self, generation: u64, line: String) {
+        let parsed = LogLine::parse(&line);
+        match &parsed {
+            LogLine {
+                conn: Some(conn),
+                op: Some(op),
+                op_type: Some(op_type),
+                message,
+            } => debug!(target: SLAPD_LOG_TARGET, conn, op, op_type, "{message}"),
+            LogLine {
+                conn: Some(conn),
+                message,
+                ..
+            } => debug!(target: SLAPD_LOG_TARGET, conn, "{message}"),
+            LogLine { message, .. } => debug!(target: SLAPD_LOG_TARGET, "{message}"),
+        }
+        if generation == self.generation.load(Ordering::SeqCst) {
+            self.operations.record(&parsed);
+        }
+
+        let mut tail = self.tail.lock().unwrap();
+        if tail.len() == LOG_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+
+    pub(crate) fn tail(&self) -> Vec<String> {
+        self.tail.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Keep reading slapd stderr until the process closes it, so slapd never blocks on a full pipe
+///
+/// Reader runs on background runtime of [`runtime`], servers shared between tests outlive
+/// runtime of the test which started them.
+pub(crate) fn forward_log(
+    mut lines: Lines<BufReader<ChildStderr>>,
+    log: Arc<SlapdLog>,
+    generation: u64,
+) {
+    runtime().spawn(async move {
+        while let Ok(Some(line)) = lines.next_line().await {
+            log.record_of(generation, line);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_operation_line() {
+        let line = LogLine::parse(
+            "6710f0a1.2e4c1f3a 0x7f8b2c000b80 conn=1000 op=1 SRCH base=\"dc=planetexpress,dc=com\" scope=2 deref=0 filter=\"(objectClass=*)\"",
+        );
+
+        assert_eq!(line.conn, Some(1000));
+        assert_eq!(line.op, Some(1));
+        assert_eq!(line.op_type, Some("SRCH"));
+        assert!(line.message.starts_with("conn=1000 op=1 SRCH base="));
+    }
+
+    #[test]
+    fn parse_connection_and_plain_lines() {
+        let line = LogLine::parse(
+            "6710f0a1.2e4c1f3a 0x7f8b2c000b80 conn=1001 fd=12 ACCEPT from IP=127.0.0.1:54321 (IP=127.0.0.1:389)",
+        );
+        assert_eq!(line.conn, Some(1001));
+        assert_eq!(line.op, None);
+        assert_eq!(line.op_type, Some("ACCEPT"));
+
+        let line = LogLine::parse("6710f0a1.2e4c1f3a 0x7f8b2c000b80 slapd starting");
+        assert_eq!(
+            line,
+            LogLine {
+                conn: None,
+                op: None,
+                op_type: None,
+                message: "slapd starting",
+            }
+        );
+    }
+
+    #[test]
+    fn parse_conn_in_filter() {
+        let line = LogLine::parse(
+            "6710f0a1.2e4c1f3a 0x7f8b2c000b80 conn=1002 op=3 SRCH attr=cn filter=\"(description=conn=5 op=7)\"",
+        );
+        assert_eq!(line.conn, Some(1002));
+        assert_eq!(line.op, Some(3));
+        assert_eq!(line.op_type, Some("SRCH"));
+
+        let line = LogLine::parse(
+            "6710f0a1.2e4c1f3a 0x7f8b2c000b80 mdb_search: filter=\"(description=conn=5 op=7 SRCH)\"",
+        );
+        assert_eq!(line.conn, None);
+        assert_eq!(line.op, None);
+        assert_eq!(line.op_type, None);
+        assert!(line.message.starts_with("mdb_search: "));
+    }
+
+    #[test]
+    fn operations_of_new_process() {
+        let log = SlapdLog::default();
+        let first = log.new_process();
+        log.record("conn=1000 op=1 SRCH base=\"dc=planetexpress,dc=com\" scope=2".to_string());
+        assert_eq!(log.operations.operations().len(), 1);
+
+        log.new_process();
+        assert!(log.operations.operations().is_empty());
+        // late line of stopped process
+        log.record_of(
+            first,
+            "conn=1000 op=2 DEL dn=\"dc=planetexpress,dc=com\"".to_string(),
+        );
+        assert!(log.operations.operations().is_empty());
+        assert_eq!(log.tail().len(), 2);
+    }
+}
@@ -0,0 +1,401 @@
+use crate::log::LogLine;
+use crate::tls::TlsSession;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::{timeout_at, Instant};
+
+/// Search scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// Only base object
+    Base,
+    /// Direct children of base object
+    OneLevel,
+    /// Base object and all its descendants
+    Subtree,
+    /// All descendants of base object, without base object
+    Children,
+}
+
+impl Scope {
+    fn from_stats(value: &str) -> Option<Self> {
+        match value {
+            "0" => Some(Scope::Base),
+            "1" => Some(Scope::OneLevel),
+            "2" => Some(Scope::Subtree),
+            "3" => Some(Scope::Children),
+            _ => None,
+        }
+    }
+}
+
+/// Type of LDAP operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum OperationKind {
+    /// BIND
+    Bind,
+    /// SRCH
+    Search,
+    /// ADD
+    Add,
+    /// MOD
+    Modify,
+    /// DEL
+    Delete,
+    /// MODRDN
+    ModRdn,
+    /// CMP
+    Compare,
+    /// EXT
+    Extended,
+}
+
+impl OperationKind {
+    fn from_stats(op_type: &str) -> Option<Self> {
+        match op_type {
+            "BIND" => Some(OperationKind::Bind),
+            "SRCH" => Some(OperationKind::Search),
+            "ADD" => Some(OperationKind::Add),
+            "MOD" => Some(OperationKind::Modify),
+            "DEL" => Some(OperationKind::Delete),
+            "MODRDN" => Some(OperationKind::ModRdn),
+            "CMP" => Some(OperationKind::Compare),
+            "EXT" => Some(OperationKind::Extended),
+            _ => None,
+        }
+    }
+}
+
+/// LDAP operation received by server, parsed from slapd stats log
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Operation {
+    /// Connection number
+    pub conn: u64,
+    /// Operation number within connection
+    pub op: u64,
+    /// Type of operation
+    pub kind: OperationKind,
+    /// Bind DN, search base or DN of added/modified/deleted entry
+    pub dn: Option<String>,
+    /// Search scope
+    pub scope: Option<Scope>,
+    /// Search filter
+    pub filter: Option<String>,
+    /// Requested attributes of search or modified attributes
+    pub attrs: Vec<String>,
+    /// Bind method (e.g. `SIMPLE`)
+    pub mechanism: Option<String>,
+    /// OID of extended operation
+    pub oid: Option<String>,
+    /// LDAP result code, `None` until server sends result
+    pub result: Option<u32>,
+}
+
+impl Operation {
+    fn new(conn: u64, op: u64, kind: OperationKind) -> Self {
+        Self {
+            conn,
+            op,
+            kind,
+            dn: None,
+            scope: None,
+            filter: None,
+            attrs: vec![],
+            mechanism: None,
+            oid: None,
+            result: None,
+        }
+    }
+
+    fn update(&mut self, args: &str) {
+        if let Some(attrs) = args.strip_prefix("attr=") {
+            self.attrs
+                .extend(attrs.split_whitespace().map(ToString::to_string));
+            return;
+        }
+
+        for (key, value) in stats_args(args) {
+            match key {
+                "dn" | "base" => self.dn = Some(value.to_string()),
+                "scope" => self.scope = Scope::from_stats(value),
+                "filter" => self.filter = Some(value.to_string()),
+                "mech" => self.mechanism = Some(value.to_string()),
+                "method" if self.mechanism.is_none() && value == "128" => {
+                    self.mechanism = Some("SIMPLE".to_string())
+                }
+                "oid" => self.oid = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Split `key=value key="quoted value"` arguments of stats log line
+fn stats_args(args: &str) -> Vec<(&str, &str)> {
+    let mut ret = vec![];
+    let mut rest = args.trim_start();
+    while let Some(eq) = rest.find('=') {
+        let key = &rest[..eq];
+        let after = &rest[eq + 1..];
+        let (value, next) = if let Some(quoted) = after.strip_prefix('"') {
+            // quoted value ends with quote followed by space or end of line
+            let end = quoted
+                .match_indices('"')
+                .map(|(idx, _)| idx)
+                .find(|idx| quoted[idx + 1..].is_empty() || quoted[idx + 1..].starts_with(' '))
+                .unwrap_or(quoted.len());
+            (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+        } else {
+            match after.find(' ') {
+                Some(end) => (&after[..end], &after[end..]),
+                None => (after, ""),
+            }
+        };
+        ret.push((key, value));
+        rest = next.trim_start();
+    }
+    ret
+}
+
+/// Operations recorded from slapd stats log
+#[derive(Debug, Default)]
+pub(crate) struct OperationRecorder {
+    operations: Mutex<Vec<Operation>>,
+    tls_sessions: Mutex<Vec<TlsSession>>,
+    notify: Notify,
+}
+
+impl OperationRecorder {
+    pub(crate) fn record(&self, line: &LogLine) {
+        if let (Some(conn), None, Some("TLS")) = (line.conn, line.op, line.op_type) {
+            self.record_tls(conn, line.message);
+            return;
+        }
+
+        let (Some(conn), Some(op), Some(op_type)) = (line.conn, line.op, line.op_type) else {
+            return;
+        };
+        // skip "conn=N op=N TYPE "
+        let args = line
+            .message
+            .splitn(4, ' ')
+            .nth(3)
+            .unwrap_or_default()
+            .trim_start();
+
+        let mut operations = self.operations.lock().unwrap();
+        let existing = operations
+            .iter_mut()
+            .rev()
+            .find(|o| o.conn == conn && o.op == op);
+
+        match (op_type, existing) {
+            ("RESULT", Some(operation)) | ("SEARCH", Some(operation)) => {
+                let result = stats_args(args.trim_start_matches("RESULT "))
+                    .into_iter()
+                    .find(|(key, _)| *key == "err")
+                    .and_then(|(_, value)| value.parse().ok());
+                operation.result = result;
+            }
+            (op_type, Some(operation))
+                if OperationKind::from_stats(op_type) == Some(operation.kind) =>
+            {
+                operation.update(args);
+            }
+            (op_type, _) => {
+                let Some(kind) = OperationKind::from_stats(op_type) else {
+                    return;
+                };
+                let mut operation = Operation::new(conn, op, kind);
+                operation.update(args);
+                operations.push(operation);
+            }
+        }
+        drop(operations);
+
+        self.notify.notify_waiters();
+    }
+
+    /// "conn=N fd=N TLS established tls_ssf=256 ssf=256 tls_proto=TLSv1.3 tls_cipher=..."
+    fn record_tls(&self, conn: u64, message: &str) {
+        let Some((_, args)) = message.split_once(" TLS established") else {
+            return;
+        };
+        let mut session = TlsSession {
+            conn,
+            ssf: None,
+            protocol: None,
+            cipher: None,
+        };
+        for (key, value) in stats_args(args) {
+            match key {
+                "tls_ssf" => session.ssf = value.parse().ok(),
+                "tls_proto" => session.protocol = Some(value.to_string()),
+                "tls_cipher" => session.cipher = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        self.tls_sessions.lock().unwrap().push(session);
+
+        self.notify.notify_waiters();
+    }
+
+    pub(crate) fn tls_sessions(&self) -> Vec<TlsSession> {
+        self.tls_sessions.lock().unwrap().clone()
+    }
+
+    pub(crate) async fn wait_for_tls(
+        &self,
+        conn: Option<u64>,
+        timeout: Duration,
+    ) -> Option<TlsSession> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // register before checking, so no notification is lost
+            let notified = self.notify.notified();
+            if let Some(session) = self
+                .tls_sessions
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|s| conn.map_or(true, |conn| s.conn == conn))
+            {
+                return Some(session.clone());
+            }
+
+            if timeout_at(deadline, notified).await.is_err() {
+                return None;
+            }
+        }
+    }
+
+    pub(crate) fn operations(&self) -> Vec<Operation> {
+        self.operations.lock().unwrap().clone()
+    }
+
+    pub(crate) fn clear(&self) {
+        self.operations.lock().unwrap().clear();
+        self.tls_sessions.lock().unwrap().clear();
+    }
+
+    pub(crate) async fn wait_for<F>(&self, predicate: F, timeout: Duration) -> Option<Operation>
+    where
+        F: Fn(&Operation) -> bool,
+    {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // register before checking, so no notification is lost
+            let notified = self.notify.notified();
+            if let Some(operation) = self
+                .operations
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|o| predicate(o))
+            {
+                return Some(operation.clone());
+            }
+
+            if timeout_at(deadline, notified).await.is_err() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_lines(lines: &[&str]) -> Vec<Operation> {
+        let recorder = OperationRecorder::default();
+        for line in lines {
+            recorder.record(&LogLine::parse(line));
+        }
+        recorder.operations()
+    }
+
+    #[test]
+    fn record_bind_and_search() {
+        let operations = record_lines(&[
+            "conn=1000 fd=12 ACCEPT from IP=127.0.0.1:54321 (IP=127.0.0.1:389)",
+            "conn=1000 op=0 BIND dn=\"cn=admin,dc=planetexpress,dc=com\" method=128",
+            "conn=1000 op=0 BIND dn=\"cn=admin,dc=planetexpress,dc=com\" mech=SIMPLE bind_ssf=0 ssf=0",
+            "conn=1000 op=0 RESULT tag=97 err=0 qtime=0.000009 etime=0.000114 text=",
+            "conn=1000 op=1 SRCH base=\"ou=people,dc=planetexpress,dc=com\" scope=2 deref=0 filter=\"(&(objectClass=person)(cn=Philip J. Fry))\"",
+            "conn=1000 op=1 SRCH attr=cn mail",
+            "conn=1000 op=1 SEARCH RESULT tag=101 err=0 qtime=0.000012 etime=0.000210 nentries=1 text=",
+        ]);
+
+        assert_eq!(operations.len(), 2);
+        assert_eq!(operations[0].kind, OperationKind::Bind);
+        assert_eq!(
+            operations[0].dn.as_deref(),
+            Some("cn=admin,dc=planetexpress,dc=com")
+        );
+        assert_eq!(operations[0].mechanism.as_deref(), Some("SIMPLE"));
+        assert_eq!(operations[0].result, Some(0));
+
+        assert_eq!(operations[1].kind, OperationKind::Search);
+        assert_eq!(operations[1].scope, Some(Scope::Subtree));
+        assert_eq!(
+            operations[1].filter.as_deref(),
+            Some("(&(objectClass=person)(cn=Philip J. Fry))")
+        );
+        assert_eq!(operations[1].attrs, vec!["cn", "mail"]);
+        assert_eq!(operations[1].result, Some(0));
+    }
+
+    #[test]
+    fn record_modifications() {
+        let operations = record_lines(&[
+            "conn=1001 op=1 ADD dn=\"cn=Amy Wong,ou=people,dc=planetexpress,dc=com\"",
+            "conn=1001 op=1 RESULT tag=105 err=68 text=Already exists",
+            "conn=1001 op=2 MOD dn=\"cn=Amy Wong,ou=people,dc=planetexpress,dc=com\"",
+            "conn=1001 op=2 MOD attr=displayName",
+            "conn=1001 op=3 EXT oid=1.3.6.1.4.1.4203.1.11.3",
+            "conn=1001 op=3 WHOAMI",
+            "conn=1001 op=4 UNBIND",
+        ]);
+
+        assert_eq!(operations.len(), 3);
+        assert_eq!(operations[0].kind, OperationKind::Add);
+        assert_eq!(operations[0].result, Some(68));
+        assert_eq!(operations[1].kind, OperationKind::Modify);
+        assert_eq!(operations[1].attrs, vec!["displayName"]);
+        assert_eq!(operations[1].result, None);
+        assert_eq!(operations[2].kind, OperationKind::Extended);
+        assert_eq!(
+            operations[2].oid.as_deref(),
+            Some("1.3.6.1.4.1.4203.1.11.3")
+        );
+    }
+
+    #[test]
+    fn record_tls_sessions() {
+        let recorder = OperationRecorder::default();
+        for line in [
+            "conn=1002 fd=13 ACCEPT from IP=127.0.0.1:54322 (IP=127.0.0.1:389)",
+            "conn=1002 op=0 EXT oid=1.3.6.1.4.1.1466.20037",
+            "conn=1002 op=0 STARTTLS",
+            "conn=1002 op=0 RESULT oid= err=0 qtime=0.000010 etime=0.000060 text=",
+            "conn=1002 fd=13 TLS established tls_ssf=256 ssf=256 tls_proto=TLSv1.3 tls_cipher=TLS_AES_256_GCM_SHA384",
+        ] {
+            recorder.record(&LogLine::parse(line));
+        }
+
+        assert_eq!(
+            recorder.tls_sessions(),
+            vec![TlsSession {
+                conn: 1002,
+                ssf: Some(256),
+                protocol: Some("TLSv1.3".to_string()),
+                cipher: Some("TLS_AES_256_GCM_SHA384".to_string()),
+            }]
+        );
+        assert_eq!(recorder.operations().len(), 1);
+    }
+}
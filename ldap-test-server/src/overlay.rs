@@ -0,0 +1,301 @@
+use std::path::Path;
+use tokio::fs;
+
+/// Overlay of database 1 configured by [`crate::LdapServerBuilder::overlay`]
+///
+/// Overlays of the same kind are merged into one overlay instance, e.g. attributes
+/// of all [`Overlay::Unique`] are unique.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Overlay {
+    /// Maintain attribute with groups of entry in member entries (`memberof` overlay)
+    MemberOf {
+        /// Object class of groups, e.g. `groupOfNames`
+        group_oc: String,
+        /// Attribute of groups with member DNs, e.g. `member`
+        member_ad: String,
+        /// Attribute maintained in member entries, e.g. `memberOf`
+        member_of_ad: String,
+        /// Update groups when member entry is renamed or deleted
+        refint: bool,
+    },
+    /// Update DNs in attributes, when referred entry is renamed or deleted (`refint` overlay)
+    RefInt {
+        /// Attributes with DNs, e.g. `member`
+        attrs: Vec<String>,
+    },
+    /// Reject entries with attribute values used by other entry (`unique` overlay)
+    Unique {
+        /// Attributes unique in database, e.g. `uid`
+        attrs: Vec<String>,
+    },
+    /// Password policy (`ppolicy` overlay), usually enabled by
+    /// [`crate::LdapServerBuilder::password_policy`]
+    PasswordPolicy {
+        /// DN of policy entry applied to entries without `pwdPolicySubentry`
+        default_policy: Option<String>,
+    },
+    /// Provide changes to syncrepl consumers (`syncprov` overlay), usually enabled by
+    /// [`crate::LdapServerBuilder::run_replicated`]
+    SyncProv,
+    /// Reject values of attribute breaking constraint (`constraint` overlay)
+    Constraint {
+        /// Constrained attribute
+        attr: String,
+        /// Constraint of values
+        constraint: Constraint,
+    },
+}
+
+impl Overlay {
+    /// `memberOf` of `groupOfNames` members, groups are updated when member is deleted
+    pub fn member_of() -> Self {
+        Overlay::MemberOf {
+            group_oc: "groupOfNames".to_string(),
+            member_ad: "member".to_string(),
+            member_of_ad: "memberOf".to_string(),
+            refint: true,
+        }
+    }
+
+    /// Referential integrity of given attributes
+    pub fn ref_int(attrs: &[&str]) -> Self {
+        Overlay::RefInt {
+            attrs: attrs.iter().map(|attr| attr.to_string()).collect(),
+        }
+    }
+
+    /// Uniqueness of given attributes
+    pub fn unique(attrs: &[&str]) -> Self {
+        Overlay::Unique {
+            attrs: attrs.iter().map(|attr| attr.to_string()).collect(),
+        }
+    }
+
+    /// Constraint of attribute values
+    pub fn constraint(attr: &str, constraint: Constraint) -> Self {
+        Overlay::Constraint {
+            attr: attr.to_string(),
+            constraint,
+        }
+    }
+
+    /// Name of overlay, its module and config object class
+    fn kind(&self) -> (&'static str, &'static str) {
+        match self {
+            Overlay::MemberOf { .. } => ("memberof", "olcMemberOf"),
+            Overlay::RefInt { .. } => ("refint", "olcRefintConfig"),
+            Overlay::Unique { .. } => ("unique", "olcUniqueConfig"),
+            Overlay::PasswordPolicy { .. } => ("ppolicy", "olcPPolicyConfig"),
+            Overlay::SyncProv => ("syncprov", "olcSyncProvConfig"),
+            Overlay::Constraint { .. } => ("constraint", "olcConstraintConfig"),
+        }
+    }
+
+    /// Config attributes of overlay
+    fn attributes(&self) -> Vec<String> {
+        match self {
+            Overlay::MemberOf {
+                group_oc,
+                member_ad,
+                member_of_ad,
+                refint,
+            } => vec![
+                format!("olcMemberOfGroupOC: {group_oc}"),
+                format!("olcMemberOfMemberAD: {member_ad}"),
+                format!("olcMemberOfMemberOfAD: {member_of_ad}"),
+                format!(
+                    "olcMemberOfRefInt: {}",
+                    if *refint { "TRUE" } else { "FALSE" }
+                ),
+                "olcMemberOfDangling: ignore".to_string(),
+            ],
+            Overlay::RefInt { attrs } => attrs
+                .iter()
+                .map(|attr| format!("olcRefintAttribute: {attr}"))
+                .collect(),
+            Overlay::Unique { attrs } => attrs
+                .iter()
+                .map(|attr| format!("olcUniqueURI: ldap:///?{attr}?sub"))
+                .collect(),
+            Overlay::PasswordPolicy { default_policy } => {
+                let mut attributes = vec!["olcPPolicyUseLockout: TRUE".to_string()];
+                if let Some(dn) = default_policy {
+                    attributes.push(format!("olcPPolicyDefault: {dn}"));
+                }
+                attributes
+            }
+            Overlay::SyncProv => vec![],
+            Overlay::Constraint { attr, constraint } => {
+                vec![format!(
+                    "olcConstraintAttribute: {attr} {}",
+                    constraint.value()
+                )]
+            }
+        }
+    }
+}
+
+/// Constraint of [`Overlay::Constraint`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Constraint {
+    /// Values match POSIX extended regular expression
+    Regex(String),
+    /// Values do not match POSIX extended regular expression
+    NegRegex(String),
+    /// Values are DNs of entries returned by LDAP URL, e.g. `ldap:///ou=groups,dc=example,dc=com?cn?sub?(objectClass=groupOfNames)`
+    Uri(String),
+    /// Maximal size of value in bytes
+    Size(usize),
+    /// Maximal number of values
+    Count(usize),
+}
+
+impl Constraint {
+    fn value(&self) -> String {
+        match self {
+            Constraint::Regex(regex) => format!("regex {}", quote(regex)),
+            Constraint::NegRegex(regex) => format!("negregex {}", quote(regex)),
+            Constraint::Uri(uri) => format!("uri {}", quote(uri)),
+            Constraint::Size(size) => format!("size {size}"),
+            Constraint::Count(count) => format!("count {count}"),
+        }
+    }
+}
+
+/// Quote value for slapd config parser, which removes backslashes
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Overlays merged by kind, in order of first occurrence, the last one of single
+/// instance overlays wins
+fn merge(overlays: &[Overlay]) -> Vec<(&'static str, &'static str, Vec<String>)> {
+    let mut merged: Vec<(&'static str, &'static str, Vec<String>)> = vec![];
+    for overlay in overlays {
+        let (name, object_class) = overlay.kind();
+        let attributes = overlay.attributes();
+        match merged.iter_mut().find(|(merged, _, _)| *merged == name) {
+            Some((_, _, merged))
+                if matches!(
+                    overlay,
+                    Overlay::MemberOf { .. } | Overlay::PasswordPolicy { .. } | Overlay::SyncProv
+                ) =>
+            {
+                *merged = attributes
+            }
+            Some((_, _, merged)) => merged.extend(attributes),
+            None => merged.push((name, object_class, attributes)),
+        }
+    }
+    merged
+}
+
+/// Modules of overlays found in module directory, overlays without module file
+/// are expected to be built into slapd
+pub(crate) async fn modules(overlays: &[Overlay], module_dir: &Path) -> Vec<&'static str> {
+    let mut modules = vec![];
+    for (name, _, _) in merge(overlays) {
+        for extension in ["la", "so"] {
+            if fs::metadata(module_dir.join(format!("{name}.{extension}")))
+                .await
+                .is_ok()
+            {
+                modules.push(name);
+                break;
+            }
+        }
+    }
+    modules
+}
+
+/// LDIF of database 0 loading modules and adding overlays to database 1
+pub(crate) fn config_ldif(overlays: &[Overlay], modules: &[&str], module_dir: &Path) -> String {
+    let mut ldif = String::new();
+    if !modules.is_empty() {
+        // slapd appends index after modules of init.ldif and included config LDIFs
+        ldif.push_str(&format!(
+            "dn: cn=module,cn=config\nobjectClass: olcModuleList\ncn: module\nolcModulePath: {}\n",
+            module_dir.display()
+        ));
+        for module in modules {
+            ldif.push_str(&format!("olcModuleLoad: {module}\n"));
+        }
+        ldif.push('\n');
+    }
+
+    for (idx, (name, object_class, attributes)) in merge(overlays).into_iter().enumerate() {
+        ldif.push_str(&format!(
+            "dn: olcOverlay={{{idx}}}{name},olcDatabase={{1}}mdb,cn=config\n\
+             objectClass: olcOverlayConfig\n\
+             objectClass: {object_class}\n\
+             olcOverlay: {{{idx}}}{name}\n"
+        ));
+        for attribute in attributes {
+            ldif.push_str(&attribute);
+            ldif.push('\n');
+        }
+        ldif.push('\n');
+    }
+    ldif
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlay_ldif() {
+        let overlays = [
+            Overlay::member_of(),
+            Overlay::unique(&["uid"]),
+            Overlay::constraint(
+                "mail",
+                Constraint::Regex("^[^@]+@planetexpress\\.com$".into()),
+            ),
+            Overlay::unique(&["mail"]),
+            Overlay::constraint("userPassword", Constraint::Count(1)),
+        ];
+        assert_eq!(
+            config_ldif(
+                &overlays,
+                &["memberof", "unique"],
+                Path::new("/usr/lib/ldap")
+            ),
+            "dn: cn=module,cn=config
+objectClass: olcModuleList
+cn: module
+olcModulePath: /usr/lib/ldap
+olcModuleLoad: memberof
+olcModuleLoad: unique
+
+dn: olcOverlay={0}memberof,olcDatabase={1}mdb,cn=config
+objectClass: olcOverlayConfig
+objectClass: olcMemberOf
+olcOverlay: {0}memberof
+olcMemberOfGroupOC: groupOfNames
+olcMemberOfMemberAD: member
+olcMemberOfMemberOfAD: memberOf
+olcMemberOfRefInt: TRUE
+olcMemberOfDangling: ignore
+
+dn: olcOverlay={1}unique,olcDatabase={1}mdb,cn=config
+objectClass: olcOverlayConfig
+objectClass: olcUniqueConfig
+olcOverlay: {1}unique
+olcUniqueURI: ldap:///?uid?sub
+olcUniqueURI: ldap:///?mail?sub
+
+dn: olcOverlay={2}constraint,olcDatabase={1}mdb,cn=config
+objectClass: olcOverlayConfig
+objectClass: olcConstraintConfig
+olcOverlay: {2}constraint
+olcConstraintAttribute: mail regex \"^[^@]+@planetexpress\\\\.com$\"
+olcConstraintAttribute: userPassword count 1
+
+"
+        );
+        assert_eq!(config_ldif(&[], &[], Path::new("/usr/lib/ldap")), "");
+    }
+}
@@ -0,0 +1,448 @@
+use crate::{Entry, LdapServerBuilder, LdapServerConn, LdapServerError, Scope};
+use rand::Rng;
+use std::sync::{Arc, Mutex, Once, TryLockError};
+use tokio::process::Child;
+use tokio::runtime::{Builder, Handle};
+use tokio::sync::OnceCell;
+use tracing::warn;
+
+type SharedServer = Arc<OnceCell<Arc<LdapServerConn>>>;
+
+/// Runtime owning shared servers, test runtimes are dropped after each test
+/// together with tasks forwarding slapd log. Its thread lives as long as process,
+/// so it also forks all slapd processes.
+static RUNTIME: Mutex<Option<Handle>> = Mutex::new(None);
+
+/// Servers stopped on process exit
+static SERVERS: Mutex<Vec<Arc<LdapServerConn>>> = Mutex::new(vec![]);
+
+/// Servers started by [`shared_server`] by base DN
+static SHARED: Mutex<Vec<(String, SharedServer)>> = Mutex::new(vec![]);
+
+/// Lazily started LDAP server shared by all tests in process
+///
+/// Server is started by first [`LdapServerPool::lease`] and stopped when process exits.
+/// Each lease gets its own `ou=test-<random>` entry under base DN of server, so tests
+/// running concurrently do not see each other's entries.
+///
+/// # Examples
+///
+/// ```
+/// use ldap_test_server::{LdapServerBuilder, LdapServerPool};
+///
+/// static POOL: LdapServerPool = LdapServerPool::new(|| {
+///     LdapServerBuilder::new("dc=planetexpress,dc=com").add(
+///         1,
+///         "dn: dc=planetexpress,dc=com
+/// objectclass: dcObject
+/// objectclass: organization
+/// o: Planet Express
+/// dc: planetexpress",
+///     )
+/// });
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let lease = POOL.lease().await;
+/// lease
+///     .add(&format!(
+///         "dn: cn=Bender,{}
+/// objectClass: person
+/// cn: Bender
+/// sn: Rodriguez",
+///         lease.base_dn()
+///     ))
+///     .await;
+/// assert!(lease.exists(&format!("cn=Bender,{}", lease.base_dn())).await);
+/// # }
+/// ```
+pub struct LdapServerPool {
+    builder: fn() -> LdapServerBuilder,
+    server: OnceCell<Arc<LdapServerConn>>,
+}
+
+impl LdapServerPool {
+    /// Pool of server built by `builder`, builder must create base DN entry
+    pub const fn new(builder: fn() -> LdapServerBuilder) -> Self {
+        Self {
+            builder,
+            server: OnceCell::const_new(),
+        }
+    }
+
+    /// Get isolated subtree on shared server, server is started on first call
+    pub async fn lease(&self) -> LdapLease {
+        self.try_lease().await.unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Get isolated subtree on shared server, returning error instead of panicking
+    pub async fn try_lease(&self) -> Result<LdapLease, LdapServerError> {
+        let server = start_server(&self.server, self.builder).await?;
+        LdapLease::create(server).await
+    }
+}
+
+/// Get isolated subtree on server with given base DN shared by all tests in process
+///
+/// Base DN entry is created automatically for `dc`, `o`, `ou` and `cn` naming attributes.
+/// Use [`LdapServerPool`] for servers with custom configuration.
+///
+/// # Examples
+///
+/// ```
+/// use ldap_test_server::shared_server;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let lease = shared_server("dc=planetexpress,dc=com").await;
+/// assert!(lease.base_dn().ends_with(",dc=planetexpress,dc=com"));
+/// assert!(lease.exists(lease.base_dn()).await);
+/// # }
+/// ```
+pub async fn shared_server(base_dn: &str) -> LdapLease {
+    try_shared_server(base_dn)
+        .await
+        .unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Get isolated subtree on shared server, returning error instead of panicking
+pub async fn try_shared_server(base_dn: &str) -> Result<LdapLease, LdapServerError> {
+    let cell = {
+        let mut shared = SHARED.lock().unwrap();
+        match shared
+            .iter()
+            .find(|(dn, _)| dn.eq_ignore_ascii_case(base_dn))
+        {
+            Some((_, cell)) => cell.clone(),
+            None => {
+                let cell = SharedServer::default();
+                shared.push((base_dn.to_string(), cell.clone()));
+                cell
+            }
+        }
+    };
+
+    let base_dn = base_dn.to_string();
+    let server = cell
+        .get_or_try_init(|| async move {
+            let base_entry = base_entry(&base_dn);
+            let builder = LdapServerBuilder::new(&base_dn);
+            let builder = match base_entry {
+                Some(ldif) => builder.add(1, &ldif),
+                None => builder,
+            };
+            run_in_background(builder).await
+        })
+        .await?
+        .clone();
+
+    LdapLease::create(server).await
+}
+
+async fn start_server(
+    cell: &OnceCell<Arc<LdapServerConn>>,
+    builder: fn() -> LdapServerBuilder,
+) -> Result<Arc<LdapServerConn>, LdapServerError> {
+    cell.get_or_try_init(|| run_in_background(builder()))
+        .await
+        .cloned()
+}
+
+/// Start server on background runtime, which lives as long as process
+async fn run_in_background(
+    builder: LdapServerBuilder,
+) -> Result<Arc<LdapServerConn>, LdapServerError> {
+    let server = runtime()
+        .spawn(builder.try_run())
+        .await
+        .unwrap_or_else(|e| panic!("{e}"))?;
+    let server = Arc::new(server);
+
+    static AT_EXIT: Once = Once::new();
+    // SAFETY: atexit only stores pointer to handler, which is `extern "C"` function
+    // without arguments living as long as process. Once registers it a single time.
+    AT_EXIT.call_once(|| unsafe {
+        libc::atexit(stop_servers);
+    });
+    SERVERS.lock().unwrap().push(server.clone());
+
+    Ok(server)
+}
+
+pub(crate) fn runtime() -> Handle {
+    let mut handle = RUNTIME.lock().unwrap();
+    handle
+        .get_or_insert_with(|| {
+            let runtime = Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("shared server runtime");
+            let handle = runtime.handle().clone();
+            std::thread::Builder::new()
+                .name("ldap-test-server".to_string())
+                .spawn(move || runtime.block_on(std::future::pending::<()>()))
+                .expect("shared server thread");
+            handle
+        })
+        .clone()
+}
+
+/// Servers are owned by statics, which are never dropped
+///
+/// Runs in exit handler, when runtimes may be already shut down, so it uses only
+/// blocking std and libc calls. `Child::id` just reads stored pid, it does not need runtime.
+extern "C" fn stop_servers() {
+    // thread calling exit could hold the lock, waiting for it would deadlock
+    let servers = match SERVERS.try_lock() {
+        Ok(servers) => servers,
+        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(TryLockError::WouldBlock) => return,
+    };
+    for server in servers.iter() {
+        if let Some(pid) = server.server.as_ref().and_then(Child::id) {
+            // SAFETY: sending signal has no memory safety requirements, pid is of slapd
+            // child, which is not reaped before process exits
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+        if !server.keep_dir.keep() {
+            let _ = std::fs::remove_dir_all(server.dir.path());
+        }
+    }
+}
+
+/// LDIF of base DN entry based on naming attribute of its first RDN
+fn base_entry(base_dn: &str) -> Option<String> {
+    let rdn = base_dn.split(',').next()?;
+    let (attr, value) = rdn.split_once('=')?;
+    let (attr, value) = (attr.trim(), value.trim());
+    let object_classes: &[&str] = match attr.to_ascii_lowercase().as_str() {
+        "dc" => &["dcObject", "organization"],
+        "o" => &["organization"],
+        "ou" => &["organizationalUnit"],
+        "cn" => &["organizationalRole"],
+        _ => return None,
+    };
+
+    let mut ldif = format!("dn: {base_dn}\n");
+    for object_class in object_classes {
+        ldif.push_str(&format!("objectClass: {object_class}\n"));
+    }
+    ldif.push_str(&format!("{attr}: {value}\n"));
+    if attr.eq_ignore_ascii_case("dc") {
+        ldif.push_str(&format!("o: {value}\n"));
+    }
+    Some(ldif)
+}
+
+/// Isolated subtree of shared LDAP server, deleted with all entries in background after drop
+///
+/// Searches and counts are limited to leased subtree. Use [`LdapLease::server`]
+/// for operations on whole shared server, its [`LdapServerConn::base_dn`] is base DN
+/// of server, while [`LdapLease::base_dn`] is DN of leased subtree.
+#[derive(Debug)]
+pub struct LdapLease {
+    server: Arc<LdapServerConn>,
+    base_dn: String,
+}
+
+impl LdapLease {
+    async fn create(server: Arc<LdapServerConn>) -> Result<Self, LdapServerError> {
+        let id: u128 = rand::thread_rng().gen();
+        let ou = format!("test-{id:032x}");
+        let base_dn = format!("ou={ou},{}", server.base_dn());
+        server
+            .try_add(&format!(
+                "dn: {base_dn}\nobjectClass: organizationalUnit\nou: {ou}\n"
+            ))
+            .await?;
+
+        Ok(Self { server, base_dn })
+    }
+
+    /// DN of leased subtree
+    pub fn base_dn(&self) -> &str {
+        &self.base_dn
+    }
+
+    /// Shared server, operations on it are not limited to leased subtree
+    pub fn server(&self) -> &LdapServerConn {
+        &self.server
+    }
+
+    /// Return URL (schema=ldap, host and port) of shared server
+    pub fn url(&self) -> &str {
+        self.server.url()
+    }
+
+    /// Return host of shared server
+    pub fn host(&self) -> &str {
+        self.server.host()
+    }
+
+    /// Return port of shared server
+    pub fn port(&self) -> u16 {
+        self.server.port()
+    }
+
+    /// Return URL (schema=ldaps, host and port) of shared server
+    pub fn ssl_url(&self) -> &str {
+        self.server.ssl_url()
+    }
+
+    /// Return SSL port of shared server
+    pub fn ssl_port(&self) -> u16 {
+        self.server.ssl_port()
+    }
+
+    /// Return CA certificate of shared server
+    pub fn ca_cert_pem(&self) -> &str {
+        self.server.ca_cert_pem()
+    }
+
+    /// Return root DN of shared server
+    pub fn root_dn(&self) -> &str {
+        self.server.root_dn()
+    }
+
+    /// Return root password of shared server
+    pub fn root_pw(&self) -> &str {
+        self.server.root_pw()
+    }
+
+    /// Apply LDIF from text
+    pub async fn add(&self, ldif_text: &str) -> &Self {
+        self.try_add(ldif_text)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Apply LDIF from text, returning error instead of panicking
+    pub async fn try_add(&self, ldif_text: &str) -> Result<&Self, LdapServerError> {
+        self.server.try_add(ldif_text).await?;
+        Ok(self)
+    }
+
+    /// Apply modification LDIF from text
+    pub async fn modify(&self, ldif_text: &str) -> &Self {
+        self.try_modify(ldif_text)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Apply modification LDIF from text, returning error instead of panicking
+    pub async fn try_modify(&self, ldif_text: &str) -> Result<&Self, LdapServerError> {
+        self.server.try_modify(ldif_text).await?;
+        Ok(self)
+    }
+
+    /// Apply delete LDIF from text
+    pub async fn delete(&self, ldif_text: &str) -> &Self {
+        self.try_delete(ldif_text)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Apply delete LDIF from text, returning error instead of panicking
+    pub async fn try_delete(&self, ldif_text: &str) -> Result<&Self, LdapServerError> {
+        self.server.try_delete(ldif_text).await?;
+        Ok(self)
+    }
+
+    /// Search entries in leased subtree, scope is relative to [`LdapLease::base_dn`]
+    pub async fn search(&self, scope: Scope, filter: &str, attrs: &[&str]) -> Vec<Entry> {
+        self.try_search(scope, filter, attrs)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Search entries in leased subtree, returning error instead of panicking
+    pub async fn try_search(
+        &self,
+        scope: Scope,
+        filter: &str,
+        attrs: &[&str],
+    ) -> Result<Vec<Entry>, LdapServerError> {
+        self.server
+            .try_search(&self.base_dn, scope, filter, attrs)
+            .await
+    }
+
+    /// Read entry by DN
+    pub async fn get(&self, dn: &str) -> Option<Entry> {
+        self.try_get(dn).await.unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Read entry by DN, returning error instead of panicking
+    pub async fn try_get(&self, dn: &str) -> Result<Option<Entry>, LdapServerError> {
+        self.server.try_get(dn).await
+    }
+
+    /// Check if entry exists
+    pub async fn exists(&self, dn: &str) -> bool {
+        self.try_exists(dn).await.unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Check if entry exists, returning error instead of panicking
+    pub async fn try_exists(&self, dn: &str) -> Result<bool, LdapServerError> {
+        self.server.try_exists(dn).await
+    }
+
+    /// Count entries matching filter in leased subtree, including its base entry
+    pub async fn count(&self, filter: &str) -> usize {
+        self.try_count(filter)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Count entries matching filter in leased subtree, returning error instead of panicking
+    pub async fn try_count(&self, filter: &str) -> Result<usize, LdapServerError> {
+        let entries = self.try_search(Scope::Subtree, filter, &["1.1"]).await?;
+        Ok(entries.len())
+    }
+}
+
+impl Drop for LdapLease {
+    fn drop(&mut self) {
+        let server = self.server.clone();
+        let base_dn = std::mem::take(&mut self.base_dn);
+        // not waited for, drop may run on background runtime itself or block worker of
+        // async test, other leases never see this subtree anyway
+        runtime().spawn(async move {
+            if let Err(e) = server.try_delete_tree(&base_dn).await {
+                warn!("failed to delete leased subtree {base_dn}: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_entries() {
+        assert_eq!(
+            base_entry("dc=planetexpress,dc=com").as_deref(),
+            Some(
+                "dn: dc=planetexpress,dc=com
+objectClass: dcObject
+objectClass: organization
+dc: planetexpress
+o: planetexpress
+"
+            )
+        );
+        assert_eq!(
+            base_entry("ou=crew, o=Planet Express").as_deref(),
+            Some(
+                "dn: ou=crew, o=Planet Express
+objectClass: organizationalUnit
+ou: crew
+"
+            )
+        );
+        assert_eq!(base_entry("uid=fry"), None);
+    }
+}
@@ -0,0 +1,98 @@
+use rand::Rng;
+use random_port::{PortPicker, Protocol};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use tracing::debug;
+
+/// Attempts to find free port not used by this or other processes
+const PICK_ATTEMPTS: usize = 100;
+
+/// Port reserved for this process with lock file shared by all processes of the user,
+/// lock is released when file is closed
+#[derive(Debug)]
+pub(crate) struct PortLock {
+    _file: File,
+}
+
+impl PortLock {
+    /// Lock port without waiting, returns `None` when port is reserved by other server
+    fn try_lock(port: u16) -> io::Result<Option<Self>> {
+        let dir = lock_dir();
+        std::fs::create_dir_all(&dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(format!("{port}.lock")))?;
+
+        // SAFETY: file descriptor is valid as long as file is open
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+            Ok(Some(Self { _file: file }))
+        } else {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::WouldBlock {
+                Ok(None)
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
+
+fn lock_dir() -> PathBuf {
+    std::env::temp_dir().join("ldap-test-server-ports")
+}
+
+/// Pick free TCP port other than `taken`, port is locked when `reserve` is set
+pub(crate) fn pick_port(
+    host: &str,
+    taken: &[u16],
+    reserve: bool,
+) -> io::Result<(u16, Option<PortLock>)> {
+    let port_picker = PortPicker::new()
+        .host(host.to_string())
+        .protocol(Protocol::Tcp)
+        .random(true);
+
+    for _ in 0..PICK_ATTEMPTS {
+        let port = port_picker.pick().unwrap_or_else(|_| {
+            let mut rng = rand::thread_rng();
+            rng.gen_range(15000..55000)
+        });
+        if taken.contains(&port) {
+            continue;
+        }
+        if !reserve {
+            return Ok((port, None));
+        }
+        match PortLock::try_lock(port)? {
+            Some(lock) => return Ok((port, Some(lock))),
+            None => debug!("port {port} is reserved by other server"),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AddrInUse,
+        format!("no free port found in {PICK_ATTEMPTS} attempts"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve_port() {
+        let (port, lock) = pick_port("127.0.0.1", &[], true).unwrap();
+        assert!(lock.is_some());
+        assert!(PortLock::try_lock(port).unwrap().is_none());
+
+        let (other, _) = pick_port("127.0.0.1", &[port], true).unwrap();
+        assert_ne!(other, port);
+
+        drop(lock);
+        assert!(PortLock::try_lock(port).unwrap().is_some());
+    }
+}
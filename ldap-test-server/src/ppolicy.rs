@@ -0,0 +1,215 @@
+use crate::cache::version_number;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Value of `pwdAccountLockedTime` locking account until administrator unlocks it
+pub(crate) const LOCKED_PERMANENTLY: &str = "000001010000Z";
+
+/// Default password policy of [`crate::LdapServerBuilder::password_policy`]
+///
+/// Policy is stored in `cn=passwordPolicy` entry under base DN and applies to
+/// `userPassword` of all entries. Options not set are not limited.
+///
+/// # Examples
+///
+/// ```
+/// use ldap_test_server::PasswordPolicy;
+/// use std::time::Duration;
+///
+/// let policy = PasswordPolicy::new()
+///     .max_failures(3)
+///     .lockout_duration(Duration::from_secs(300))
+///     .max_age(Duration::from_secs(90 * 24 * 3600))
+///     .grace_logins(2)
+///     .history(5)
+///     .min_length(8)
+///     .must_change(true);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    max_failures: u32,
+    lockout_duration: Option<Duration>,
+    max_age: Option<Duration>,
+    grace_logins: u32,
+    history: u32,
+    min_length: u32,
+    must_change: bool,
+}
+
+impl PasswordPolicy {
+    /// Policy without any limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lock account after number of consecutive failed binds (`pwdMaxFailure`)
+    pub fn max_failures(mut self, failures: u32) -> Self {
+        self.max_failures = failures;
+        self
+    }
+
+    /// How long account stays locked (`pwdLockoutDuration`), until administrator
+    /// unlocks it by default
+    pub fn lockout_duration(mut self, duration: Duration) -> Self {
+        self.lockout_duration = Some(duration);
+        self
+    }
+
+    /// Password expires after given time since it was changed (`pwdMaxAge`)
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Number of binds allowed with expired password (`pwdGraceAuthNLimit`)
+    pub fn grace_logins(mut self, logins: u32) -> Self {
+        self.grace_logins = logins;
+        self
+    }
+
+    /// Number of previous passwords, which can not be reused (`pwdInHistory`)
+    pub fn history(mut self, passwords: u32) -> Self {
+        self.history = passwords;
+        self
+    }
+
+    /// Minimal password length (`pwdMinLength`), passwords which can not be checked
+    /// (e.g. hashed by client) are rejected
+    pub fn min_length(mut self, length: u32) -> Self {
+        self.min_length = length;
+        self
+    }
+
+    /// User has to change password after it was set by administrator (`pwdMustChange`)
+    pub fn must_change(mut self, must_change: bool) -> Self {
+        self.must_change = must_change;
+        self
+    }
+
+    /// LDIF of policy entry
+    pub(crate) fn ldif(&self, dn: &str) -> String {
+        let mut ldif = format!(
+            "dn: {dn}
+objectClass: device
+objectClass: pwdPolicy
+cn: passwordPolicy
+pwdAttribute: userPassword
+pwdAllowUserChange: TRUE
+"
+        );
+        if self.max_failures > 0 {
+            ldif.push_str(&format!(
+                "pwdLockout: TRUE\npwdMaxFailure: {}\n",
+                self.max_failures
+            ));
+            let duration = self.lockout_duration.unwrap_or_default();
+            ldif.push_str(&format!("pwdLockoutDuration: {}\n", duration.as_secs()));
+        }
+        if let Some(age) = self.max_age {
+            ldif.push_str(&format!("pwdMaxAge: {}\n", age.as_secs()));
+        }
+        if self.grace_logins > 0 {
+            ldif.push_str(&format!("pwdGraceAuthNLimit: {}\n", self.grace_logins));
+        }
+        if self.history > 0 {
+            ldif.push_str(&format!("pwdInHistory: {}\n", self.history));
+        }
+        if self.min_length > 0 {
+            ldif.push_str(&format!(
+                "pwdCheckQuality: 2\npwdMinLength: {}\n",
+                self.min_length
+            ));
+        }
+        if self.must_change {
+            ldif.push_str("pwdMustChange: TRUE\n");
+        }
+        ldif
+    }
+}
+
+/// OpenLDAP before 2.5 needs `ppolicy.ldif` schema, newer versions have it built into
+/// overlay, `version` is output of `slapd -VV`
+pub(crate) fn schema_required(version: &str) -> bool {
+    matches!(version_number(version), Some(version) if version < (2, 5))
+}
+
+/// LDAP generalized time (UTC) of given time
+pub(crate) fn generalized_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}{:02}{:02}{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_ldif() {
+        assert_eq!(
+            PasswordPolicy::new()
+                .max_failures(3)
+                .max_age(Duration::from_secs(3600))
+                .min_length(8)
+                .ldif("cn=passwordPolicy,dc=planetexpress,dc=com"),
+            "dn: cn=passwordPolicy,dc=planetexpress,dc=com
+objectClass: device
+objectClass: pwdPolicy
+cn: passwordPolicy
+pwdAttribute: userPassword
+pwdAllowUserChange: TRUE
+pwdLockout: TRUE
+pwdMaxFailure: 3
+pwdLockoutDuration: 0
+pwdMaxAge: 3600
+pwdCheckQuality: 2
+pwdMinLength: 8
+"
+        );
+    }
+
+    #[test]
+    fn ppolicy_schema() {
+        assert!(schema_required(
+            "@(#) $OpenLDAP: slapd 2.4.57+dfsg-3 (Jan 30 2023 06:40:05) $"
+        ));
+        assert!(!schema_required(
+            "@(#) $OpenLDAP: slapd 2.5.13+dfsg-5 (Feb  8 2023 01:55:31) $"
+        ));
+        assert!(!schema_required(
+            "@(#) $OpenLDAP: slapd 2.6.8 (Jun  1 2024) $"
+        ));
+    }
+
+    #[test]
+    fn generalized_times() {
+        assert_eq!(generalized_time(UNIX_EPOCH), "19700101000000Z");
+        assert_eq!(
+            generalized_time(UNIX_EPOCH + Duration::from_secs(951_782_400 + 3723)),
+            "20000229010203Z"
+        );
+        assert_eq!(
+            generalized_time(UNIX_EPOCH + Duration::from_secs(1_767_225_599)),
+            "20251231235959Z"
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use tokio::io::copy_bidirectional;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::{AbortHandle, JoinHandle};
+use tracing::debug;
+
+/// TCP proxy of replication connections from one node to another, which can be cut
+/// without stopping either server
+#[derive(Debug)]
+pub(crate) struct LinkProxy {
+    port: u16,
+    listener: Option<TcpListener>,
+    state: Arc<Mutex<LinkState>>,
+    task: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug, Default)]
+struct LinkState {
+    cut: bool,
+    connections: Vec<AbortHandle>,
+}
+
+impl LinkProxy {
+    /// Listen on random port, connections wait for [`LinkProxy::start`]
+    pub(crate) async fn bind(host: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind((host, 0)).await?;
+        Ok(Self {
+            port: listener.local_addr()?.port(),
+            listener: Some(listener),
+            state: Arc::default(),
+            task: None,
+        })
+    }
+
+    pub(crate) fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Forward connections to `target` (`host:port`)
+    pub(crate) fn start(&mut self, target: String) {
+        let Some(listener) = self.listener.take() else {
+            return;
+        };
+        let state = self.state.clone();
+        self.task = Some(tokio::spawn(async move {
+            loop {
+                let mut client = match listener.accept().await {
+                    Ok((client, _)) => client,
+                    Err(e) => {
+                        debug!("proxy to {target} stopped: {e}");
+                        return;
+                    }
+                };
+                // hold lock, so cut never misses new connection
+                let mut state = state.lock().unwrap();
+                if state.cut {
+                    continue;
+                }
+                let target = target.clone();
+                let connection = tokio::spawn(async move {
+                    match TcpStream::connect(&target).await {
+                        Ok(mut server) => {
+                            let _ = copy_bidirectional(&mut client, &mut server).await;
+                        }
+                        Err(e) => debug!("proxy failed to connect to {target}: {e}"),
+                    }
+                });
+                state.connections.retain(|c| !c.is_finished());
+                state.connections.push(connection.abort_handle());
+            }
+        }));
+    }
+
+    /// Close forwarded connections and refuse new ones
+    pub(crate) fn cut(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.cut = true;
+        for connection in state.connections.drain(..) {
+            connection.abort();
+        }
+    }
+
+    /// Forward new connections again
+    pub(crate) fn heal(&self) {
+        self.state.lock().unwrap().cut = false;
+    }
+
+    pub(crate) fn is_cut(&self) -> bool {
+        self.state.lock().unwrap().cut
+    }
+}
+
+impl Drop for LinkProxy {
+    fn drop(&mut self) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+        self.cut();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn cut_and_heal() {
+        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = echo.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = echo.accept().await {
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = stream.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+
+        let mut proxy = LinkProxy::bind("127.0.0.1").await.unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", proxy.port()))
+            .await
+            .unwrap();
+        proxy.start(target);
+
+        let mut buf = [0; 4];
+        stream.write_all(b"ping").await.unwrap();
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        proxy.cut();
+        assert!(proxy.is_cut());
+        assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+        let mut stream = TcpStream::connect(("127.0.0.1", proxy.port()))
+            .await
+            .unwrap();
+        assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+
+        proxy.heal();
+        let mut stream = TcpStream::connect(("127.0.0.1", proxy.port()))
+            .await
+            .unwrap();
+        stream.write_all(b"pong").await.unwrap();
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+    }
+}
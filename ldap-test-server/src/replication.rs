@@ -0,0 +1,246 @@
+use crate::cache::version_number;
+use crate::{LdapServerConn, LdapServerError, Scope};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Interval between `contextCSN` checks of [`LdapReplicaSet::wait_for_replication`] and
+/// [`crate::LdapCluster::wait_for_replication`]
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Provider server with `syncprov` overlay and consumers replicating its database 1
+///
+/// Started by [`crate::LdapServerBuilder::run_replicated`]. Consumers use `refreshAndPersist`
+/// replication bound as root DN of provider and refer updates to provider, so changes
+/// should be made on provider.
+///
+/// # Examples
+///
+/// ```
+/// use ldap_test_server::LdapServerBuilder;
+/// use std::time::Duration;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let replicas = LdapServerBuilder::new("dc=planetexpress,dc=com")
+///     .add(1, "dn: dc=planetexpress,dc=com
+/// objectclass: dcObject
+/// objectclass: organization
+/// o: Planet Express
+/// dc: planetexpress")
+///     .run_replicated(1)
+///     .await;
+/// replicas
+///     .provider()
+///     .add(
+///         "dn: cn=Bender,dc=planetexpress,dc=com
+/// objectClass: person
+/// cn: Bender
+/// sn: Rodriguez",
+///     )
+///     .await;
+/// assert!(replicas.wait_for_replication(Duration::from_secs(10)).await);
+/// assert!(replicas.consumer(0).exists("cn=Bender,dc=planetexpress,dc=com").await);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LdapReplicaSet {
+    provider: LdapServerConn,
+    consumers: Vec<LdapServerConn>,
+}
+
+impl LdapReplicaSet {
+    pub(crate) fn new(provider: LdapServerConn, consumers: Vec<LdapServerConn>) -> Self {
+        Self {
+            provider,
+            consumers,
+        }
+    }
+
+    /// Provider server
+    pub fn provider(&self) -> &LdapServerConn {
+        &self.provider
+    }
+
+    /// Provider server, e.g. to stop or restart it
+    pub fn provider_mut(&mut self) -> &mut LdapServerConn {
+        &mut self.provider
+    }
+
+    /// All consumer servers
+    pub fn consumers(&self) -> &[LdapServerConn] {
+        &self.consumers
+    }
+
+    /// Consumer server by index, panics when index is out of range
+    pub fn consumer(&self, index: usize) -> &LdapServerConn {
+        &self.consumers[index]
+    }
+
+    /// Consumer server by index, e.g. to stop or restart it
+    pub fn consumer_mut(&mut self, index: usize) -> &mut LdapServerConn {
+        &mut self.consumers[index]
+    }
+
+    /// Wait until `contextCSN` of base DN entry is the same on provider and all running
+    /// consumers, returns `false` on timeout
+    pub async fn wait_for_replication(&self, timeout: Duration) -> bool {
+        self.try_wait_for_replication(timeout)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Wait until replication converges, returning error instead of panicking
+    pub async fn try_wait_for_replication(
+        &self,
+        timeout: Duration,
+    ) -> Result<bool, LdapServerError> {
+        wait_until_converged(timeout, || self.converged()).await
+    }
+
+    async fn converged(&self) -> Result<bool, LdapServerError> {
+        let expected = context_csn(&self.provider).await?;
+        for consumer in self.consumers.iter().filter(|c| c.is_running()) {
+            if context_csn(consumer).await? != expected {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Poll `converged` until it returns `true`, returns `false` on timeout
+pub(crate) async fn wait_until_converged<F, Fut>(
+    timeout: Duration,
+    mut converged: F,
+) -> Result<bool, LdapServerError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<bool, LdapServerError>>,
+{
+    let started = Instant::now();
+    loop {
+        if converged().await? {
+            return Ok(true);
+        }
+        if started.elapsed() >= timeout {
+            return Ok(false);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// URL consumers replicate from, TLS is required by provider for simple bind of
+/// consumers when it requires StartTLS
+pub(crate) fn provider_url(tls_required: bool, host: &str, port: u16) -> String {
+    let scheme = if tls_required { "ldaps" } else { "ldap" };
+    format!("{scheme}://{host}:{port}")
+}
+
+/// Port of provider URL of `server`, see [`provider_url`]
+pub(crate) fn provider_port(server: &LdapServerConn) -> u16 {
+    if server.tls_required {
+        server.ssl_port()
+    } else {
+        server.port()
+    }
+}
+
+/// Sorted `contextCSN` values of base DN entry, one per server ID
+pub(crate) async fn context_csn(server: &LdapServerConn) -> Result<Vec<Vec<u8>>, LdapServerError> {
+    let entries = server
+        .try_search(
+            server.base_dn(),
+            Scope::Base,
+            "(objectClass=*)",
+            &["contextCSN"],
+        )
+        .await?;
+    let mut csn = entries
+        .first()
+        .and_then(|entry| entry.attr("contextCSN"))
+        .map(<[_]>::to_vec)
+        .unwrap_or_default();
+    csn.sort();
+    Ok(csn)
+}
+
+/// Replication of database 1 configured by [`crate::LdapServerBuilder::run_replicated`]
+/// and [`crate::LdapServerBuilder::run_cluster`]
+#[derive(Debug, Clone)]
+pub(crate) struct Syncrepl {
+    /// Replication ID and URL of each provider
+    pub(crate) providers: Vec<(u16, String)>,
+    /// Server ID of multi-provider node, consumer refers updates to provider otherwise
+    pub(crate) server_id: Option<u16>,
+    /// Entries of database 1 come from providers, so LDIFs are not added
+    pub(crate) replica: bool,
+}
+
+/// Attribute enabling multi-provider replication, named `olcMirrorMode` before 2.5
+pub(crate) fn multi_provider_attribute(version: &str) -> &'static str {
+    match version_number(version) {
+        Some(version) if version < (2, 5) => "olcMirrorMode",
+        _ => "olcMultiProvider",
+    }
+}
+
+/// `olcSyncrepl` value of consumer replicating database 1 of provider
+pub(crate) fn syncrepl_config(
+    rid: u16,
+    provider_url: &str,
+    base_dn: &str,
+    root_dn: &str,
+    root_pw: &str,
+) -> String {
+    let mut config = format!(
+        "rid={rid:03} provider={provider_url} bindmethod=simple binddn=\"{root_dn}\" \
+         credentials=\"{root_pw}\" searchbase=\"{base_dn}\" type=refreshAndPersist \
+         retry=\"1 +\" timeout=3"
+    );
+    if provider_url.starts_with("ldaps://") {
+        // provider certificate is signed by CA of other server
+        config.push_str(" tls_reqcert=never");
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syncrepl() {
+        assert_eq!(
+            syncrepl_config(
+                1,
+                "ldap://127.0.0.1:3890",
+                "dc=planetexpress,dc=com",
+                "cn=admin,dc=planetexpress,dc=com",
+                "secret"
+            ),
+            "rid=001 provider=ldap://127.0.0.1:3890 bindmethod=simple \
+             binddn=\"cn=admin,dc=planetexpress,dc=com\" credentials=\"secret\" \
+             searchbase=\"dc=planetexpress,dc=com\" type=refreshAndPersist retry=\"1 +\" timeout=3"
+        );
+        assert!(syncrepl_config(
+            2,
+            "ldaps://127.0.0.1:6360",
+            "o=test",
+            "cn=admin,o=test",
+            "pw"
+        )
+        .ends_with(" tls_reqcert=never"));
+    }
+
+    #[test]
+    fn multi_provider() {
+        assert_eq!(
+            multi_provider_attribute("@(#) $OpenLDAP: slapd 2.4.57+dfsg-3 (Jan 30 2023) $"),
+            "olcMirrorMode"
+        );
+        assert_eq!(
+            multi_provider_attribute("@(#) $OpenLDAP: slapd 2.6.8 (Jun  1 2024) $"),
+            "olcMultiProvider"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use crate::LdapServerError;
+use std::path::Path;
+use tokio::fs;
+
+/// Directory in server directory with Cyrus SASL configuration of slapd
+pub(crate) const SASL_CONF_DIR: &str = "sasl2";
+
+/// SASL mechanism authenticating users by `userPassword` stored in directory
+///
+/// Mechanisms are provided by Cyrus SASL plugins, which have to be installed
+/// (e.g. `libsasl2-modules` package on Debian and Ubuntu).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SaslMechanism {
+    /// PLAIN, password is sent in clear text
+    Plain,
+    /// CRAM-MD5
+    CramMd5,
+    /// DIGEST-MD5
+    DigestMd5,
+    /// SCRAM-SHA-1
+    ScramSha1,
+    /// SCRAM-SHA-256
+    ScramSha256,
+}
+
+impl SaslMechanism {
+    /// Name of mechanism
+    pub fn name(self) -> &'static str {
+        match self {
+            SaslMechanism::Plain => "PLAIN",
+            SaslMechanism::CramMd5 => "CRAM-MD5",
+            SaslMechanism::DigestMd5 => "DIGEST-MD5",
+            SaslMechanism::ScramSha1 => "SCRAM-SHA-1",
+            SaslMechanism::ScramSha256 => "SCRAM-SHA-256",
+        }
+    }
+}
+
+/// Write `slapd.conf` of Cyrus SASL, enabling only given mechanisms
+///
+/// EXTERNAL is always enabled, as it is used by `ldapi://` socket and client certificates.
+pub(crate) async fn write_config(
+    work_dir: &Path,
+    mechanisms: &[SaslMechanism],
+) -> Result<(), LdapServerError> {
+    let dir = work_dir.join(SASL_CONF_DIR);
+    fs::create_dir_all(&dir).await?;
+
+    let mut mech_list = vec!["EXTERNAL"];
+    mech_list.extend(mechanisms.iter().map(|mechanism| mechanism.name()));
+    fs::write(
+        dir.join("slapd.conf"),
+        format!("mech_list: {}\n", mech_list.join(" ")),
+    )
+    .await?;
+    Ok(())
+}
+
+/// `olcAuthzRegexp` value mapping SASL user name (with optional realm) to entry
+/// with the same `uid` under base DN
+pub(crate) fn user_authz_regexp(base_dn: &str) -> String {
+    format!("\"^uid=([^,]+)(,cn=[^,]+)?,cn=[^,]+,cn=auth$\" \"ldap:///{base_dn}??sub?(uid=$1)\"")
+}
+
+/// LDIF of entry with clear text password usable by all mechanisms
+pub(crate) fn user_ldif(dn: &str, password: &str) -> Result<String, LdapServerError> {
+    let uid = dn
+        .split(',')
+        .next()
+        .and_then(|rdn| rdn.split_once('='))
+        .filter(|(attr, _)| attr.trim().eq_ignore_ascii_case("uid"))
+        .map(|(_, value)| value.trim())
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| LdapServerError::InvalidDn(dn.to_string()))?;
+
+    Ok(format!(
+        "dn: {dn}
+objectClass: account
+objectClass: simpleSecurityObject
+uid: {uid}
+userPassword: {password}
+"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sasl_user() {
+        assert_eq!(
+            user_ldif("uid=fry,ou=people,dc=planetexpress,dc=com", "secret").unwrap(),
+            "dn: uid=fry,ou=people,dc=planetexpress,dc=com
+objectClass: account
+objectClass: simpleSecurityObject
+uid: fry
+userPassword: secret
+"
+        );
+        assert!(user_ldif("cn=fry,dc=planetexpress,dc=com", "secret").is_err());
+        assert_eq!(
+            user_authz_regexp("dc=planetexpress,dc=com"),
+            "\"^uid=([^,]+)(,cn=[^,]+)?,cn=[^,]+,cn=auth$\" \
+             \"ldap:///dc=planetexpress,dc=com??sub?(uid=$1)\""
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::collections::BTreeMap;
+use std::io;
+
+/// LDAP entry returned by [`crate::LdapServerConn::search`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entry {
+    /// Distinguished name of entry
+    pub dn: String,
+    /// Attribute values by attribute name
+    pub attrs: BTreeMap<String, Vec<Vec<u8>>>,
+}
+
+impl Entry {
+    /// Values of attribute, attribute name is case insensitive
+    pub fn attr(&self, name: &str) -> Option<&[Vec<u8>]> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, values)| values.as_slice())
+    }
+
+    /// First value of attribute as text, `None` if attribute is missing or value is not UTF-8
+    pub fn attr_str(&self, name: &str) -> Option<&str> {
+        self.attr(name)
+            .and_then(|values| values.first())
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    /// Parse entries from LDIF printed by `ldapsearch -LLL`
+    pub(crate) fn parse_ldif(ldif: &str) -> io::Result<Vec<Entry>> {
+        let mut entries = vec![];
+        let mut entry: Option<Entry> = None;
+
+        for line in unfold(ldif) {
+            if line.is_empty() {
+                entries.extend(entry.take());
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(invalid_ldif(&line));
+            };
+            let value = if let Some(encoded) = value.strip_prefix(':') {
+                STANDARD
+                    .decode(encoded.trim())
+                    .map_err(|_| invalid_ldif(&line))?
+            } else {
+                value.trim_start().as_bytes().to_vec()
+            };
+
+            match &mut entry {
+                None if name == "dn" => {
+                    entry = Some(Entry {
+                        dn: String::from_utf8(value).map_err(|_| invalid_ldif(&line))?,
+                        attrs: BTreeMap::new(),
+                    })
+                }
+                None => return Err(invalid_ldif(&line)),
+                Some(entry) => entry.attrs.entry(name.to_string()).or_default().push(value),
+            }
+        }
+        entries.extend(entry);
+
+        Ok(entries)
+    }
+}
+
+/// Join continuation lines (starting with single space) of LDIF
+pub(crate) fn unfold(ldif: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for line in ldif.lines() {
+        match (line.strip_prefix(' '), lines.last_mut()) {
+            (Some(continuation), Some(last)) if !last.is_empty() => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn invalid_ldif(line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid LDIF line: {line}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_entries() {
+        let entries = Entry::parse_ldif(
+            "dn: cn=Philip J. Fry,ou=people,dc=planetexpress,dc=com
+objectClass: inetOrgPerson
+objectClass: person
+cn: Philip J. Fry
+description:: RGVsaXZlcnkgYm95IOKAkyBodW1hbg==
+
+dn:: Y249QW15IFdvbmcsb3U9cGVvcGxlLGRjPXBsYW5ldGV4cHJlc3MsZGM9Y29t
+cn: Amy
+  Wong
+",
+        )
+        .unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0].dn,
+            "cn=Philip J. Fry,ou=people,dc=planetexpress,dc=com"
+        );
+        assert_eq!(entries[0].attr("objectclass").unwrap().len(), 2);
+        assert_eq!(
+            entries[0].attr_str("description"),
+            Some("Delivery boy – human")
+        );
+        assert_eq!(
+            entries[1].dn,
+            "cn=Amy Wong,ou=people,dc=planetexpress,dc=com"
+        );
+        assert_eq!(entries[1].attr_str("cn"), Some("Amy Wong"));
+    }
+}
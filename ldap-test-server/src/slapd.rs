@@ -0,0 +1,428 @@
+use crate::builder::POSSIBLE_SCHEMA_DIR;
+use crate::log::{forward_log, SlapdLog};
+use crate::pool::runtime;
+use crate::LdapServerError;
+use std::io;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command};
+use tokio::time::{sleep, timeout};
+use tracing::{debug, warn};
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// File in server directory with pid of process owning the server
+pub(crate) const OWNER_PID_FILE: &str = "ldap-test-server-owner.pid";
+
+/// Files in server directory describing running processes, not valid in copies of directory
+pub(crate) const PROCESS_FILES: &[&str] = &["slapd.pid", "slapd.args", OWNER_PID_FILE];
+
+/// OpenLDAP commands executed by this crate
+const REQUIRED_BINARIES: &[&str] = &[
+    "slapd",
+    "slapadd",
+    "slapcat",
+    "ldapadd",
+    "ldapmodify",
+    "ldapdelete",
+    "ldapsearch",
+];
+
+/// Arguments of slapd process, kept to start server again from the same directory
+#[derive(Debug, Clone)]
+pub(crate) struct SlapdCommand {
+    pub(crate) config_dir: PathBuf,
+    pub(crate) urls: String,
+    pub(crate) debug_level: i32,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    /// Directory with Cyrus SASL configuration, passed in `SASL_CONF_PATH`
+    pub(crate) sasl_conf_path: Option<PathBuf>,
+}
+
+impl SlapdCommand {
+    /// Command line for starting slapd by hand
+    pub(crate) fn command_line(&self) -> String {
+        let env = match &self.sasl_conf_path {
+            Some(path) => format!("SASL_CONF_PATH=\"{}\" ", path.display()),
+            None => String::new(),
+        };
+        format!(
+            "{env}slapd -F \"{}\" -d {} -h \"{}\"",
+            self.config_dir.display(),
+            self.debug_level,
+            self.urls
+        )
+    }
+
+    /// Launch slapd and wait until it accepts connections
+    pub(crate) async fn start(&self, log: &Arc<SlapdLog>) -> Result<Child, LdapServerError> {
+        let host = &self.host;
+        let port = self.port;
+        let generation = log.new_process();
+
+        let mut command = Command::new("slapd");
+        command
+            .arg("-F")
+            .arg(&self.config_dir)
+            .arg("-d")
+            .arg(self.debug_level.to_string())
+            .arg("-h")
+            .arg(&self.urls)
+            .stderr(Stdio::piped());
+        if let Some(path) = &self.sasl_conf_path {
+            command.env("SASL_CONF_PATH", path);
+        }
+
+        // kill slapd when thread which started it exits, also when test process is aborted
+        // (thread of background runtime lives as long as process)
+        #[cfg(target_os = "linux")]
+        // SAFETY: prctl is async-signal-safe
+        unsafe {
+            command.pre_exec(|| {
+                if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        // callers may run on short-lived threads (test threads, blocking workers of
+        // multi-thread runtime), so slapd is forked by background runtime thread
+        let mut server = runtime()
+            .spawn(async move { command.spawn() })
+            .await
+            // background runtime is shutting down
+            .map_err(|e| LdapServerError::Io(e.into()))?
+            .map_err(|e| LdapServerError::spawn("slapd", e))?;
+
+        // wait until slapd server has started
+        let stderr = server.stderr.take().unwrap();
+        let mut lines = BufReader::new(stderr).lines();
+        let started = Instant::now();
+        loop {
+            if let Some(status) = server.try_wait()? {
+                // collect what slapd managed to write before exiting
+                let _ = timeout(Duration::from_secs(1), async {
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        log.record(line);
+                    }
+                })
+                .await;
+                return Err(exited(status, log.tail()));
+            }
+
+            if started.elapsed() > STARTUP_TIMEOUT {
+                let _ = server.kill().await;
+                return Err(LdapServerError::StartupTimeout { log: log.tail() });
+            }
+
+            match timeout(Duration::from_millis(100), lines.next_line()).await {
+                Ok(Ok(Some(line))) => {
+                    let is_started = line.ends_with("slapd starting");
+                    log.record(line);
+                    if is_started {
+                        break;
+                    }
+                }
+                Ok(Ok(None)) => {
+                    // stderr closed, slapd is exiting
+                    let status = server.wait().await?;
+                    return Err(exited(status, log.tail()));
+                }
+                Ok(Err(e)) => {
+                    let _ = server.kill().await;
+                    return Err(e.into());
+                }
+                Err(_) => {}
+            }
+        }
+        forward_log(lines, log.clone(), generation);
+
+        let timeouted = timeout(STARTUP_TIMEOUT, async {
+            while !is_tcp_port_open(host, port).await {
+                if let Ok(Some(status)) = server.try_wait() {
+                    return Some(status);
+                }
+                debug!("tcp port {port} is not open yet, waiting...");
+                sleep(Duration::from_micros(100)).await;
+            }
+            None
+        })
+        .await;
+
+        match timeouted {
+            Ok(None) => {}
+            Ok(Some(status)) => {
+                return Err(exited(status, log.tail()));
+            }
+            Err(_) => {
+                let _ = server.kill().await;
+                return Err(LdapServerError::PortNotOpen {
+                    port,
+                    log: log.tail(),
+                });
+            }
+        }
+
+        debug!("Started ldap server on {}", self.urls);
+
+        Ok(server)
+    }
+}
+
+/// Error of slapd which exited during startup
+fn exited(status: ExitStatus, log: Vec<String>) -> LdapServerError {
+    // e.g. "daemon: bind(8) failed errno=98 (Address already in use)"
+    if log
+        .iter()
+        .any(|line| line.contains("errno=98") || line.contains("Address already in use"))
+    {
+        LdapServerError::AddressInUse { log }
+    } else {
+        LdapServerError::ServerExited { status, log }
+    }
+}
+
+/// Send SIGTERM to slapd and wait for exit, slapd is killed if it does not exit in time
+pub(crate) async fn stop(server: &mut Child) -> Result<ExitStatus, LdapServerError> {
+    if let Some(pid) = server.id() {
+        // SAFETY: pid is of child process which has not been reaped yet
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == -1 {
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() == Some(libc::ESRCH) {
+                // already gone, nothing to wait for
+                return Ok(server.wait().await?);
+            }
+            return Err(e.into());
+        }
+    }
+
+    match timeout(STOP_TIMEOUT, server.wait()).await {
+        Ok(status) => Ok(status?),
+        Err(_) => {
+            warn!("slapd did not stop in {STOP_TIMEOUT:?}, killing it");
+            server.kill().await?;
+            Ok(server.wait().await?)
+        }
+    }
+}
+
+/// Parent of server directories, only directory scanned by [`reap_orphans`]
+pub(crate) fn run_dir() -> PathBuf {
+    std::env::temp_dir().join("ldap-test-server-run")
+}
+
+/// Kill slapd processes left by test processes which did not exit cleanly and
+/// remove their server directories
+///
+/// Scans `ldap-test-server-run` in system temporary directory for server directories
+/// whose owner process is no longer running. Returns number of removed servers.
+///
+/// # Examples
+///
+/// ```
+/// let reaped = ldap_test_server::reap_orphans();
+/// println!("removed {reaped} orphaned LDAP servers");
+/// ```
+pub fn reap_orphans() -> usize {
+    try_reap_orphans().unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Kill orphaned slapd processes, returning error instead of panicking
+pub fn try_reap_orphans() -> Result<usize, LdapServerError> {
+    reap_orphans_in(&run_dir())
+}
+
+fn reap_orphans_in(run_dir: &Path) -> Result<usize, LdapServerError> {
+    let entries = match std::fs::read_dir(run_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut reaped = 0;
+    for entry in entries {
+        let dir = entry?.path();
+        let Some(owner) = read_pid(&dir.join(OWNER_PID_FILE)) else {
+            continue;
+        };
+        if is_alive(owner) {
+            continue;
+        }
+
+        if let Some(slapd) = read_pid(&dir.join("slapd.pid")) {
+            if is_alive(slapd) && is_slapd_of(slapd, &dir) {
+                debug!(
+                    "killing orphaned slapd pid: {slapd}, dir: {}",
+                    dir.display()
+                );
+                // SAFETY: sending signal has no memory safety requirements
+                unsafe {
+                    libc::kill(slapd, libc::SIGKILL);
+                }
+            }
+        }
+
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => reaped += 1,
+            Err(e) => warn!(
+                "failed to remove orphaned server dir {}: {e}",
+                dir.display()
+            ),
+        }
+    }
+    Ok(reaped)
+}
+
+fn read_pid(file: &Path) -> Option<libc::pid_t> {
+    std::fs::read_to_string(file).ok()?.trim().parse().ok()
+}
+
+fn is_alive(pid: libc::pid_t) -> bool {
+    // SAFETY: signal 0 only checks if process exists
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Check command line of process, pid could be reused by other process
+#[cfg(target_os = "linux")]
+fn is_slapd_of(pid: libc::pid_t, dir: &Path) -> bool {
+    std::fs::read(format!("/proc/{pid}/cmdline"))
+        .map(|cmdline| {
+            let cmdline = String::from_utf8_lossy(&cmdline);
+            cmdline.contains("slapd") && cmdline.contains(&*dir.to_string_lossy())
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_slapd_of(_pid: libc::pid_t, _dir: &Path) -> bool {
+    true
+}
+
+/// Load LDIF file to database with slapadd
+pub(crate) async fn slapadd(
+    config_dir: &Path,
+    dbnum: u8,
+    file: PathBuf,
+) -> Result<(), LdapServerError> {
+    debug!("slapadd dbnum: {dbnum} file: {}", file.display());
+
+    let db_number = dbnum.to_string();
+    // load slapd configuration
+    let output = Command::new("slapadd")
+        .arg("-F")
+        .arg(config_dir)
+        .arg("-n")
+        .arg(db_number)
+        .arg("-l")
+        .arg(&file)
+        .output()
+        .await
+        .map_err(|e| LdapServerError::spawn("slapadd", e))?;
+
+    if !output.status.success() {
+        return Err(LdapServerError::Slapadd {
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            file,
+        });
+    }
+
+    Ok(())
+}
+
+/// Dump database as LDIF with slapcat
+pub(crate) async fn slapcat(config_dir: &Path, dbnum: u8) -> Result<String, LdapServerError> {
+    let output = Command::new("slapcat")
+        .arg("-F")
+        .arg(config_dir)
+        .arg("-n")
+        .arg(dbnum.to_string())
+        .output()
+        .await
+        .map_err(|e| LdapServerError::spawn("slapcat", e))?;
+
+    if !output.status.success() {
+        return Err(LdapServerError::LdapTool {
+            command: "slapcat".to_string(),
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            file: None,
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Check if OpenLDAP server and tools are found in `$PATH` and slapd schema directory exists
+///
+/// Allows skipping tests on machines without OpenLDAP installed.
+///
+/// # Examples
+///
+/// ```
+/// use ldap_test_server::{is_installed, LdapServerBuilder};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// if !is_installed() {
+///     eprintln!("OpenLDAP is not installed, skipping test");
+///     return;
+/// }
+/// let server = LdapServerBuilder::new("dc=planetexpress,dc=com").run().await;
+/// # }
+/// ```
+pub fn is_installed() -> bool {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let found = |binary: &str| std::env::split_paths(&path).any(|dir| dir.join(binary).is_file());
+
+    REQUIRED_BINARIES.iter().all(|binary| found(binary))
+        && POSSIBLE_SCHEMA_DIR
+            .iter()
+            .any(|dir| Path::new(dir).is_dir())
+}
+
+async fn is_tcp_port_open(host: &str, port: u16) -> bool {
+    let addr = (host, port).to_socket_addrs().unwrap().next().unwrap();
+    let Ok(sock) = timeout(Duration::from_secs(1), TcpStream::connect(&addr)).await else {
+        return false;
+    };
+    sock.is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reap_dir_of_dead_owner() {
+        let mut owner = std::process::Command::new("true").spawn().unwrap();
+        owner.wait().unwrap();
+
+        let run_dir = tempfile::tempdir().unwrap();
+        let dir = run_dir.path().join("dead");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join(OWNER_PID_FILE), owner.id().to_string()).unwrap();
+        let alive = run_dir.path().join("alive");
+        std::fs::create_dir(&alive).unwrap();
+        std::fs::write(alive.join(OWNER_PID_FILE), std::process::id().to_string()).unwrap();
+        let other = run_dir.path().join("other");
+        std::fs::create_dir(&other).unwrap();
+
+        assert_eq!(reap_orphans_in(run_dir.path()).unwrap(), 1);
+        assert!(!dir.exists());
+        assert!(alive.exists());
+        assert!(other.exists());
+    }
+}
@@ -0,0 +1,101 @@
+use ldap3::{LdapConnAsync, LdapConnSettings, LdapError};
+use ldap_test_server::{CertificatePreset, LdapServerBuilder, LdapServerConn};
+use native_tls::{Certificate, TlsConnector};
+
+async fn bind(server: &LdapServerConn, url: &str, ca: Option<&str>) -> Result<(), LdapError> {
+    let mut connector = TlsConnector::builder();
+    if let Some(ca) = ca {
+        connector
+            .disable_built_in_roots(true)
+            .add_root_certificate(Certificate::from_pem(ca.as_bytes()).unwrap());
+    }
+    let settings = LdapConnSettings::new().set_connector(connector.build().unwrap());
+    let (conn, mut ldap) = LdapConnAsync::with_settings(settings, url).await?;
+    ldap3::drive!(conn);
+    ldap.simple_bind(server.root_dn(), server.root_pw())
+        .await?
+        .success()?;
+    ldap.unbind().await
+}
+
+#[tokio::test]
+async fn test_intermediate_chain() {
+    let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .intermediate_ca(true)
+        .ssl_alt_name("ldap.planetexpress.com")
+        .run()
+        .await;
+
+    let intermediate = server.intermediate_cert_pem().expect("intermediate CA");
+    assert!(server.chain_pem().starts_with(server.ssl_cert_pem()));
+    assert!(server.chain_pem().ends_with(intermediate));
+    assert_ne!(server.ca_cert_pem(), intermediate);
+
+    // server certificate is valid for listen address and localhost
+    let localhost = format!("ldaps://localhost:{}", server.ssl_port());
+    for url in [server.ssl_url(), &localhost] {
+        bind(&server, url, Some(server.ca_cert_pem()))
+            .await
+            .unwrap_or_else(|e| panic!("{url}: {e}"));
+    }
+
+    // test CA is not trusted by default
+    assert!(bind(&server, server.ssl_url(), None).await.is_err());
+}
+
+#[tokio::test]
+async fn test_root_signed() {
+    let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .run()
+        .await;
+
+    assert!(server.intermediate_cert_pem().is_none());
+    assert_eq!(server.chain_pem(), server.ssl_cert_pem());
+    bind(&server, server.ssl_url(), Some(server.ca_cert_pem()))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_invalid_certificates() {
+    for preset in [
+        CertificatePreset::Expired,
+        CertificatePreset::NotYetValid,
+        CertificatePreset::WrongHost,
+    ] {
+        let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+            .ssl_certificate_preset(preset)
+            .run()
+            .await;
+        assert!(
+            bind(&server, server.ssl_url(), Some(server.ca_cert_pem()))
+                .await
+                .is_err(),
+            "{preset:?} certificate accepted"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_rotate_certificate() {
+    let mut server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .ssl_certificate_preset(CertificatePreset::Expired)
+        .run()
+        .await;
+    let expired = server.ssl_cert_pem().to_string();
+    assert!(bind(&server, server.ssl_url(), Some(server.ca_cert_pem()))
+        .await
+        .is_err());
+
+    server.rotate_certificate().await;
+    assert_ne!(server.ssl_cert_pem(), expired);
+    bind(&server, server.ssl_url(), Some(server.ca_cert_pem()))
+        .await
+        .unwrap();
+
+    // rotated certificate is used after restart
+    server.restart().await;
+    bind(&server, server.ssl_url(), Some(server.ca_cert_pem()))
+        .await
+        .unwrap();
+}
@@ -0,0 +1,73 @@
+use ldap_test_server::{LdapServerBuilder, LdapServerConn, Scope};
+use std::time::Duration;
+
+const BASE: &str = "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress";
+
+#[tokio::test]
+async fn test_cluster() {
+    let cluster = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .add(1, BASE)
+        .run_cluster(3)
+        .await;
+    assert_eq!(cluster.nodes().len(), 3);
+    assert!(cluster.wait_for_replication(Duration::from_secs(10)).await);
+
+    // changes on any node are replicated
+    cluster
+        .node(2)
+        .add(
+            "dn: cn=Bender,dc=planetexpress,dc=com
+objectClass: person
+cn: Bender
+sn: Rodriguez",
+        )
+        .await;
+    assert!(cluster.wait_for_replication(Duration::from_secs(10)).await);
+    for node in cluster.nodes() {
+        assert!(node.exists("cn=Bender,dc=planetexpress,dc=com").await);
+    }
+
+    // conflicting changes in partition
+    cluster.isolate(0);
+    assert!(cluster.is_cut(0, 1));
+    assert!(cluster.is_cut(2, 0));
+    assert!(!cluster.is_cut(1, 2));
+    for (node, description) in [(0, "first"), (1, "second")] {
+        cluster
+            .node(node)
+            .modify(&format!(
+                "dn: cn=Bender,dc=planetexpress,dc=com
+changetype: modify
+replace: description
+description: {description}"
+            ))
+            .await;
+    }
+    assert!(!cluster.wait_for_replication(Duration::from_secs(2)).await);
+    assert_eq!(description(cluster.node(0)).await, "first");
+    assert_eq!(description(cluster.node(2)).await, "second");
+
+    // later change wins after heal
+    cluster.heal_all();
+    assert!(!cluster.is_cut(0, 1));
+    assert!(cluster.wait_for_replication(Duration::from_secs(10)).await);
+    for node in cluster.nodes() {
+        assert_eq!(description(node).await, "second");
+    }
+}
+
+async fn description(server: &LdapServerConn) -> String {
+    let entries = server
+        .search(
+            "cn=Bender,dc=planetexpress,dc=com",
+            Scope::Base,
+            "(objectClass=*)",
+            &["description"],
+        )
+        .await;
+    entries[0].attr_str("description").unwrap().to_string()
+}
@@ -25,7 +25,7 @@ pub struct LdapWorld {
 impl LdapWorld {
     async fn connect_to_ldap(&mut self) {
         let server = self.server.as_ref().unwrap();
-        let cert = Certificate::from_pem(server.ssl_cert_pem().as_bytes()).unwrap();
+        let cert = Certificate::from_pem(server.ca_cert_pem().as_bytes()).unwrap();
         let settings = LdapConnSettings::new().set_connector(
             TlsConnector::builder()
                 .add_root_certificate(cert)
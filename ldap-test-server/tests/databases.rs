@@ -0,0 +1,122 @@
+use ldap3::{LdapConnAsync, Scope as LdapScope, SearchEntry};
+use ldap_test_server::{ConfigCache, LdapServerBuilder, Scope};
+
+fn builder() -> (LdapServerBuilder, u8, u8) {
+    let mut builder = LdapServerBuilder::new("dc=planetexpress,dc=com");
+    let people = builder.add_database(
+        "ou=people,o=tenant",
+        "cn=admin,ou=people,o=tenant",
+        "people",
+    );
+    let services = builder.add_database("o=services", "cn=admin,o=services", "services");
+    let builder = builder
+        .add(
+            1,
+            "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress",
+        )
+        .add(
+            people,
+            "dn: ou=people,o=tenant
+objectClass: organizationalUnit
+ou: people
+
+dn: uid=fry,ou=people,o=tenant
+objectClass: inetOrgPerson
+uid: fry
+cn: Philip J. Fry
+sn: Fry",
+        )
+        .add(
+            services,
+            "dn: o=services
+objectClass: organization
+o: services",
+        );
+    (builder, people, services)
+}
+
+#[tokio::test]
+async fn test_databases() {
+    let (builder, people, services) = builder();
+    assert_eq!((people, services), (2, 3));
+    let server = builder.run().await;
+
+    assert!(server.exists("dc=planetexpress,dc=com").await);
+    assert!(server.exists("uid=fry,ou=people,o=tenant").await);
+    assert!(server.exists("o=services").await);
+
+    // root DN of database 1 manages all databases
+    server
+        .add(
+            "dn: cn=mail,o=services
+objectClass: device
+cn: mail",
+        )
+        .await;
+    let entries = server
+        .search("o=services", Scope::Subtree, "(cn=mail)", &["cn"])
+        .await;
+    assert_eq!(entries.len(), 1);
+
+    // root DN of added database binds with its own password
+    let (conn, mut ldap) = LdapConnAsync::new(server.url()).await.unwrap();
+    ldap3::drive!(conn);
+    ldap.simple_bind("cn=admin,ou=people,o=tenant", "people")
+        .await
+        .unwrap()
+        .success()
+        .unwrap();
+    let (entries, _) = ldap
+        .search(
+            "ou=people,o=tenant",
+            LdapScope::OneLevel,
+            "(uid=fry)",
+            vec!["cn"],
+        )
+        .await
+        .unwrap()
+        .success()
+        .unwrap();
+    let fry = SearchEntry::construct(entries.into_iter().next().unwrap());
+    assert_eq!(fry.attrs["cn"], ["Philip J. Fry"]);
+    ldap.unbind().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_cached_databases() {
+    let cache_dir = tempfile::tempdir().unwrap();
+    let cache = ConfigCache::new(cache_dir.path());
+    for _ in 0..2 {
+        let (builder, _, _) = builder();
+        let server = builder.cache(cache.clone()).run().await;
+        assert!(server.exists("uid=fry,ou=people,o=tenant").await);
+        assert!(server.exists("o=services").await);
+    }
+}
+
+#[tokio::test]
+async fn test_from_dir_database_numbers() {
+    let golden = tempfile::tempdir().unwrap();
+    let (builder, _, _) = builder();
+    builder.run().await.clone_to_dir(golden.path()).await;
+
+    // numbering continues after databases of copied config
+    let server = LdapServerBuilder::from_dir(golden.path())
+        .with_database("o=other", "cn=admin,o=other", "other", |builder, dbnum| {
+            assert_eq!(dbnum, 4);
+            builder.add(
+                dbnum,
+                "dn: o=other
+objectClass: organization
+o: other",
+            )
+        })
+        .run()
+        .await;
+    assert!(server.exists("o=services").await);
+    assert!(server.exists("o=other").await);
+}
@@ -0,0 +1,55 @@
+use ldap3::exop::{WhoAmI, WhoAmIResp};
+use ldap3::LdapConnAsync;
+use ldap_test_server::{LdapServerBuilder, StartTls};
+
+#[tokio::test]
+async fn test_ldapi_external_bind() {
+    let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .ldapi(true)
+        .run()
+        .await;
+    let ldapi_url = server.ldapi_url().expect("ldapi url");
+    assert!(ldapi_url.starts_with("ldapi://%2F"));
+
+    // internal tools use ldapi socket
+    server
+        .add(
+            "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress",
+        )
+        .await;
+    assert!(server.exists("dc=planetexpress,dc=com").await);
+
+    let (conn, mut ldap) = LdapConnAsync::new(ldapi_url).await.unwrap();
+    ldap3::drive!(conn);
+    ldap.sasl_external_bind().await.unwrap().success().unwrap();
+    let (exop, _) = ldap.extended(WhoAmI).await.unwrap().success().unwrap();
+    let whoami: WhoAmIResp = exop.parse();
+    assert_eq!(whoami.authzid, format!("dn:{}", server.root_dn()));
+
+    ldap.unbind().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_ldapi_with_required_tls() {
+    let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .ldapi(true)
+        .start_tls(StartTls::Require)
+        .run()
+        .await;
+
+    // internal tools fall back to ldaps port
+    server
+        .add(
+            "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress",
+        )
+        .await;
+    assert!(server.exists("dc=planetexpress,dc=com").await);
+}
@@ -0,0 +1,61 @@
+use ldap3::{LdapConnAsync, Scope};
+use ldap_test_server::{LdapServerBuilder, LdapServerError};
+
+#[tokio::test]
+async fn test_stop_and_start() {
+    let mut server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .add(
+            1,
+            "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress",
+        )
+        .run()
+        .await;
+    server
+        .add(
+            "dn: ou=people,dc=planetexpress,dc=com
+objectClass: organizationalUnit
+ou: people",
+        )
+        .await;
+    let port = server.port();
+
+    let status = server.stop().await;
+    assert!(status.success(), "slapd exited with {status}");
+    assert!(!server.is_running());
+    assert!(LdapConnAsync::new(server.url()).await.is_err());
+    assert!(matches!(
+        server.try_stop().await,
+        Err(LdapServerError::NotRunning)
+    ));
+
+    server.start().await;
+    assert_eq!(server.port(), port);
+
+    let (conn, mut ldap) = LdapConnAsync::new(server.url()).await.unwrap();
+    ldap3::drive!(conn);
+    ldap.simple_bind(server.root_dn(), server.root_pw())
+        .await
+        .unwrap()
+        .success()
+        .unwrap();
+    let (entries, _) = ldap
+        .search(
+            "ou=people,dc=planetexpress,dc=com",
+            Scope::Base,
+            "(objectClass=*)",
+            vec!["ou"],
+        )
+        .await
+        .unwrap()
+        .success()
+        .unwrap();
+    assert_eq!(entries.len(), 1);
+
+    server.restart().await;
+    assert!(server.is_running());
+    assert!(server.exists("ou=people,dc=planetexpress,dc=com").await);
+}
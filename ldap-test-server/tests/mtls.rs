@@ -0,0 +1,66 @@
+use ldap3::exop::{WhoAmI, WhoAmIResp};
+use ldap3::{LdapConnAsync, LdapConnSettings};
+use ldap_test_server::{LdapServerBuilder, StartTls, TlsVerifyClient};
+use native_tls::{Identity, TlsConnector};
+
+#[tokio::test]
+async fn test_client_certificate_external_bind() {
+    let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .start_tls(StartTls::Require)
+        .tls_verify_client(TlsVerifyClient::Demand)
+        .run()
+        .await;
+
+    // internal tools present their own certificate
+    server
+        .add(
+            "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress
+
+dn: uid=fry,dc=planetexpress,dc=com
+objectClass: inetOrgPerson
+uid: fry
+cn: Philip J. Fry
+sn: Fry",
+        )
+        .await;
+
+    let client = server
+        .issue_client_cert("uid=fry,dc=planetexpress,dc=com")
+        .await;
+    let identity =
+        Identity::from_pkcs8(client.cert_pem().as_bytes(), client.key_pem().as_bytes()).unwrap();
+    let connector = TlsConnector::builder()
+        .identity(identity)
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+
+    let settings = LdapConnSettings::new().set_connector(connector);
+    let (conn, mut ldap) = LdapConnAsync::with_settings(settings, server.ssl_url())
+        .await
+        .unwrap();
+    ldap3::drive!(conn);
+    ldap.sasl_external_bind().await.unwrap().success().unwrap();
+    let (exop, _) = ldap.extended(WhoAmI).await.unwrap().success().unwrap();
+    let whoami: WhoAmIResp = exop.parse();
+    assert_eq!(whoami.authzid, "dn:uid=fry,dc=planetexpress,dc=com");
+    ldap.unbind().await.unwrap();
+
+    // without client certificate TLS handshake fails
+    let connector = TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+    let settings = LdapConnSettings::new().set_connector(connector);
+    let result = async {
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, server.ssl_url()).await?;
+        ldap3::drive!(conn);
+        ldap.simple_bind(server.root_dn(), server.root_pw()).await
+    }
+    .await;
+    assert!(result.is_err());
+}
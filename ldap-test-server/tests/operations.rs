@@ -0,0 +1,61 @@
+use ldap3::{LdapConnAsync, Scope};
+use ldap_test_server::{LdapServerBuilder, OperationKind};
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_record_search() {
+    let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .add(
+            1,
+            "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress",
+        )
+        .run()
+        .await;
+
+    let (conn, mut ldap) = LdapConnAsync::new(server.url()).await.unwrap();
+    ldap3::drive!(conn);
+    ldap.simple_bind(server.root_dn(), server.root_pw())
+        .await
+        .unwrap()
+        .success()
+        .unwrap();
+    ldap.search(
+        server.base_dn(),
+        Scope::Subtree,
+        "(objectClass=organization)",
+        vec!["o"],
+    )
+    .await
+    .unwrap()
+    .success()
+    .unwrap();
+
+    let search = server
+        .wait_for_operation(
+            |o| o.kind == OperationKind::Search && o.result.is_some(),
+            Duration::from_secs(5),
+        )
+        .await
+        .expect("search operation");
+    assert_eq!(search.dn.as_deref(), Some("dc=planetexpress,dc=com"));
+    assert_eq!(search.scope, Some(ldap_test_server::Scope::Subtree));
+    assert_eq!(search.filter.as_deref(), Some("(objectClass=organization)"));
+    assert_eq!(search.attrs, vec!["o"]);
+    assert_eq!(search.result, Some(0));
+
+    let binds = server
+        .operations()
+        .into_iter()
+        .filter(|o| o.kind == OperationKind::Bind && o.conn == search.conn)
+        .count();
+    assert_eq!(binds, 1);
+
+    server.clear_operations();
+    assert!(server.operations().is_empty());
+
+    ldap.unbind().await.unwrap();
+}
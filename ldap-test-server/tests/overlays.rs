@@ -0,0 +1,94 @@
+use ldap_test_server::{Constraint, LdapServerBuilder, Overlay, Scope};
+
+#[tokio::test]
+async fn test_overlays() {
+    let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .add(
+            1,
+            "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress",
+        )
+        .overlay(Overlay::member_of())
+        .overlay(Overlay::ref_int(&["member"]))
+        .overlay(Overlay::unique(&["uid"]))
+        .overlay(Overlay::constraint(
+            "mail",
+            Constraint::Regex("^[^@]+@planetexpress\\.com$".to_string()),
+        ))
+        .run()
+        .await;
+
+    server
+        .add(
+            "dn: uid=fry,dc=planetexpress,dc=com
+objectClass: inetOrgPerson
+uid: fry
+cn: Philip J. Fry
+sn: Fry
+mail: fry@planetexpress.com
+
+dn: uid=leela,dc=planetexpress,dc=com
+objectClass: inetOrgPerson
+uid: leela
+cn: Turanga Leela
+sn: Turanga
+
+dn: cn=crew,dc=planetexpress,dc=com
+objectClass: groupOfNames
+cn: crew
+member: uid=fry,dc=planetexpress,dc=com
+member: uid=leela,dc=planetexpress,dc=com",
+        )
+        .await;
+
+    // memberof
+    let fry = server
+        .search(
+            "uid=fry,dc=planetexpress,dc=com",
+            Scope::Base,
+            "(objectClass=*)",
+            &["memberOf"],
+        )
+        .await;
+    assert_eq!(
+        fry[0].attr_str("memberOf"),
+        Some("cn=crew,dc=planetexpress,dc=com")
+    );
+
+    // refint
+    server
+        .delete("dn: uid=leela,dc=planetexpress,dc=com\nchangetype: delete")
+        .await;
+    let crew = server.get("cn=crew,dc=planetexpress,dc=com").await.unwrap();
+    assert_eq!(
+        crew.attr("member").map(<[_]>::len),
+        Some(1),
+        "deleted member stays in group"
+    );
+
+    // unique
+    assert!(server
+        .try_add(
+            "dn: cn=Philip Fry,dc=planetexpress,dc=com
+objectClass: inetOrgPerson
+uid: fry
+cn: Philip Fry
+sn: Fry"
+        )
+        .await
+        .is_err());
+
+    // constraint
+    assert!(server
+        .try_modify(
+            "dn: uid=fry,dc=planetexpress,dc=com
+changetype: modify
+replace: mail
+mail: fry@momcorp.com"
+        )
+        .await
+        .is_err());
+}
@@ -0,0 +1,64 @@
+use ldap_test_server::{shared_server, LdapServerBuilder, LdapServerPool, Scope};
+use std::time::Duration;
+
+static POOL: LdapServerPool = LdapServerPool::new(|| {
+    LdapServerBuilder::new("dc=planetexpress,dc=com").add(
+        1,
+        "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress",
+    )
+});
+
+async fn add_crew() {
+    let lease = POOL.lease().await;
+    assert!(lease.base_dn().ends_with(",dc=planetexpress,dc=com"));
+
+    lease
+        .add(&format!(
+            "dn: cn=Hermes Conrad,{}
+objectClass: person
+cn: Hermes Conrad
+sn: Conrad",
+            lease.base_dn()
+        ))
+        .await;
+
+    let entries = lease.search(Scope::OneLevel, "(objectClass=*)", &[]).await;
+    assert_eq!(entries.len(), 1);
+    // entries of concurrently running tests are not counted
+    assert_eq!(lease.count("(cn=Hermes Conrad)").await, 1);
+    assert_eq!(lease.port(), POOL.lease().await.port());
+    assert_eq!(lease.server().base_dn(), "dc=planetexpress,dc=com");
+}
+
+#[tokio::test]
+async fn test_pool_lease() {
+    add_crew().await;
+}
+
+#[tokio::test]
+async fn test_pool_lease_isolated() {
+    add_crew().await;
+}
+
+#[tokio::test]
+async fn test_shared_server_cleanup() {
+    let lease = shared_server("ou=crew,o=Planet Express").await;
+    let subtree = lease.base_dn().to_string();
+    assert!(lease.exists(&subtree).await);
+
+    drop(lease);
+    let lease = shared_server("ou=crew,o=Planet Express").await;
+    assert_ne!(lease.base_dn(), subtree);
+    // subtree is deleted in background
+    for _ in 0..50 {
+        if !lease.exists(&subtree).await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("leased subtree {subtree} was not deleted");
+}
@@ -0,0 +1,121 @@
+use ldap3::{LdapConnAsync, Scope};
+use ldap_test_server::{LdapServerBuilder, LdapServerConn, PasswordPolicy};
+use std::time::Duration;
+
+const FRY: &str = "cn=Philip J. Fry,dc=planetexpress,dc=com";
+
+/// Result code of simple bind as Fry
+async fn bind(server: &LdapServerConn, password: &str) -> u32 {
+    let (conn, mut ldap) = LdapConnAsync::new(server.url()).await.unwrap();
+    ldap3::drive!(conn);
+    let rc = ldap.simple_bind(FRY, password).await.unwrap().rc;
+    ldap.unbind().await.unwrap();
+    rc
+}
+
+async fn server(policy: PasswordPolicy) -> LdapServerConn {
+    let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .add(
+            1,
+            "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress",
+        )
+        .password_policy(policy)
+        .run()
+        .await;
+    server
+        .add(&format!(
+            "dn: {FRY}
+objectClass: person
+cn: Philip J. Fry
+sn: Fry
+userPassword: slurm"
+        ))
+        .await;
+    server
+}
+
+#[tokio::test]
+async fn test_lockout() {
+    let server = server(PasswordPolicy::new().max_failures(2)).await;
+    assert!(
+        server
+            .exists(server.password_policy_dn().expect("policy"))
+            .await
+    );
+    assert_eq!(bind(&server, "slurm").await, 0);
+
+    for _ in 0..2 {
+        assert_eq!(bind(&server, "wrong").await, 49);
+    }
+    // invalidCredentials, even with correct password
+    assert_eq!(bind(&server, "slurm").await, 49);
+
+    server.unlock_account(FRY).await;
+    assert_eq!(bind(&server, "slurm").await, 0);
+
+    server.lock_account(FRY).await;
+    assert_eq!(bind(&server, "slurm").await, 49);
+    server.unlock_account(FRY).await;
+    assert_eq!(bind(&server, "slurm").await, 0);
+}
+
+#[tokio::test]
+async fn test_expired_password() {
+    let server = server(PasswordPolicy::new().max_age(Duration::from_secs(3600))).await;
+    assert_eq!(bind(&server, "slurm").await, 0);
+
+    server
+        .backdate_password(FRY, Duration::from_secs(7200))
+        .await;
+    assert_eq!(bind(&server, "slurm").await, 49);
+}
+
+#[tokio::test]
+async fn test_password_reset() {
+    let server = server(PasswordPolicy::new().must_change(true)).await;
+    server.force_password_reset(FRY).await;
+
+    let (conn, mut ldap) = LdapConnAsync::new(server.url()).await.unwrap();
+    ldap3::drive!(conn);
+    ldap.simple_bind(FRY, "slurm")
+        .await
+        .unwrap()
+        .success()
+        .unwrap();
+    // only password change is allowed
+    let result = ldap
+        .search(server.base_dn(), Scope::Base, "(objectClass=*)", ["dn"])
+        .await
+        .unwrap();
+    assert_ne!(result.1.rc, 0);
+    ldap.unbind().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_custom_policy_entry() {
+    // policy schema is loaded before entries of database 1
+    let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .add(
+            1,
+            "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress
+
+dn: cn=strict,dc=planetexpress,dc=com
+objectClass: device
+objectClass: pwdPolicy
+cn: strict
+pwdAttribute: userPassword
+pwdMinLength: 12",
+        )
+        .password_policy(PasswordPolicy::new())
+        .run()
+        .await;
+    assert!(server.exists("cn=strict,dc=planetexpress,dc=com").await);
+}
@@ -0,0 +1,61 @@
+use ldap_test_server::LdapServerBuilder;
+use std::time::Duration;
+
+const BASE: &str = "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress";
+
+#[tokio::test]
+async fn test_replication() {
+    let mut replicas = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .add(1, BASE)
+        .run_replicated(2)
+        .await;
+    assert_eq!(replicas.consumers().len(), 2);
+    assert!(replicas.wait_for_replication(Duration::from_secs(10)).await);
+    for consumer in replicas.consumers() {
+        assert!(consumer.exists("dc=planetexpress,dc=com").await);
+    }
+
+    replicas
+        .provider()
+        .add(
+            "dn: cn=Bender,dc=planetexpress,dc=com
+objectClass: person
+cn: Bender
+sn: Rodriguez",
+        )
+        .await;
+    assert!(replicas.wait_for_replication(Duration::from_secs(10)).await);
+    for consumer in replicas.consumers() {
+        assert!(consumer.exists("cn=Bender,dc=planetexpress,dc=com").await);
+    }
+
+    // stopped consumer catches up after restart
+    replicas.consumer_mut(1).stop().await;
+    replicas
+        .provider()
+        .delete(
+            "dn: cn=Bender,dc=planetexpress,dc=com
+changetype: delete",
+        )
+        .await;
+    assert!(replicas.wait_for_replication(Duration::from_secs(10)).await);
+    assert!(
+        !replicas
+            .consumer(0)
+            .exists("cn=Bender,dc=planetexpress,dc=com")
+            .await
+    );
+
+    replicas.consumer_mut(1).start().await;
+    assert!(replicas.wait_for_replication(Duration::from_secs(10)).await);
+    assert!(
+        !replicas
+            .consumer(1)
+            .exists("cn=Bender,dc=planetexpress,dc=com")
+            .await
+    );
+}
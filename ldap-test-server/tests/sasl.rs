@@ -0,0 +1,48 @@
+use ldap_test_server::{LdapServerBuilder, LdapServerConn, SaslMechanism};
+use tokio::process::Command;
+
+async fn whoami(server: &LdapServerConn, mechanism: SaslMechanism, password: &str) -> String {
+    let output = Command::new("ldapwhoami")
+        .args(["-Q", "-Y", mechanism.name(), "-U", "fry", "-w", password])
+        .args(["-H", server.url()])
+        .output()
+        .await
+        .unwrap();
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[tokio::test]
+async fn test_sasl_bind() {
+    let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .add(
+            1,
+            "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress
+
+dn: ou=people,dc=planetexpress,dc=com
+objectclass: organizationalUnit
+ou: people",
+        )
+        .sasl_mechanism(SaslMechanism::Plain)
+        .sasl_mechanism(SaslMechanism::DigestMd5)
+        .run()
+        .await;
+    server
+        .add_sasl_user("uid=fry,ou=people,dc=planetexpress,dc=com", "slurm")
+        .await;
+
+    for mechanism in [SaslMechanism::Plain, SaslMechanism::DigestMd5] {
+        assert_eq!(
+            whoami(&server, mechanism, "slurm").await,
+            "dn:uid=fry,ou=people,dc=planetexpress,dc=com",
+            "{mechanism:?}"
+        );
+        assert_eq!(whoami(&server, mechanism, "wrong").await, "");
+    }
+
+    // only enabled mechanisms are offered
+    assert_eq!(whoami(&server, SaslMechanism::CramMd5, "slurm").await, "");
+}
@@ -6,7 +6,7 @@ async fn test_bind_over_ssl() {
     let server = LdapServerBuilder::new("dc=kondej,dc=net").run().await;
 
     let tls =
-        TlsOptions::tls().ca_cert(Certificate::from_pem(server.ssl_cert_pem().as_ref()).unwrap());
+        TlsOptions::tls().ca_cert(Certificate::from_pem(server.ca_cert_pem().as_ref()).unwrap());
 
     let mut client = LdapClient::builder(server.host())
         .port(server.ssl_port())
@@ -0,0 +1,62 @@
+use ldap3::{LdapConnAsync, LdapConnSettings};
+use ldap_test_server::{LdapServerBuilder, OperationKind, StartTls, TlsVersion};
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_require_starttls() {
+    let server = LdapServerBuilder::new("dc=planetexpress,dc=com")
+        .start_tls(StartTls::Require)
+        .tls_min_version(TlsVersion::Tls1_2)
+        .run()
+        .await;
+
+    // internal tools work with TLS required
+    server
+        .add(
+            "dn: dc=planetexpress,dc=com
+objectclass: dcObject
+objectclass: organization
+o: Planet Express
+dc: planetexpress",
+        )
+        .await;
+
+    let (conn, mut ldap) = LdapConnAsync::new(server.url()).await.unwrap();
+    ldap3::drive!(conn);
+    let result = ldap
+        .simple_bind(server.root_dn(), server.root_pw())
+        .await
+        .unwrap();
+    // confidentialityRequired
+    assert_eq!(result.rc, 13);
+
+    server.clear_operations();
+    let settings = LdapConnSettings::new()
+        .set_starttls(true)
+        .set_no_tls_verify(true);
+    let (conn, mut ldap) = LdapConnAsync::with_settings(settings, server.url())
+        .await
+        .unwrap();
+    ldap3::drive!(conn);
+    ldap.simple_bind(server.root_dn(), server.root_pw())
+        .await
+        .unwrap()
+        .success()
+        .unwrap();
+
+    let bind = server
+        .wait_for_operation(
+            |o| o.kind == OperationKind::Bind && o.result == Some(0),
+            Duration::from_secs(5),
+        )
+        .await
+        .expect("bind operation");
+    let session = server
+        .wait_for_tls(Some(bind.conn), Duration::from_secs(5))
+        .await
+        .expect("TLS session");
+    assert!(session.ssf.unwrap_or_default() > 0);
+    assert!(server.tls_session(bind.conn).is_some());
+
+    ldap.unbind().await.unwrap();
+}